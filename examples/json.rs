@@ -3,6 +3,7 @@ use std::io;
 use elegance::{
     core::Printer,
     render::{Io, Render},
+    ListStyle,
 };
 
 enum Value {
@@ -22,36 +23,16 @@ impl Value {
             Value::Bool(false) => pp.text("false")?,
             Value::Number(x) => pp.text(format!("{}", x))?,
             Value::String(s) => pp.text(format!("\"{}\"", s))?,
-            Value::Array(arr) => pp.igroup(2, |pp| {
-                pp.text("[")?;
-                if let Some((first, rest)) = arr.split_first() {
-                    pp.zero_break()?;
-                    first.print(pp)?;
-                    for v in rest {
-                        pp.text(",")?;
-                        pp.space()?;
-                        v.print(pp)?;
-                    }
-                    pp.scan_break(0, -2)?;
-                }
-                pp.text("]")
-            })?,
-            Value::Object(obj) => pp.cgroup(2, |pp| {
-                let mut obj = obj.iter();
-                pp.text("{")?;
-                if let Some((k, v)) = obj.next() {
-                    pp.zero_break()?;
-                    pp.text(format!("\"{}\": ", k))?;
-                    v.print(pp)?;
-                    for (k, v) in obj {
-                        pp.text(",")?;
-                        pp.space()?;
-                        pp.text(format!("\"{}\": ", k))?;
-                        v.print(pp)?;
-                    }
-                    pp.scan_break(0, -2)?;
-                }
-                pp.text("}")
+            Value::Array(arr) => {
+                let style = ListStyle {
+                    consistent: false,
+                    ..Default::default()
+                };
+                pp.delimited_with(style, "[", "]", 2, arr, ",", |pp, v| v.print(pp))?
+            }
+            Value::Object(obj) => pp.delimited("{", "}", 2, obj, ",", |pp, (k, v)| {
+                pp.text(format!("\"{}\": ", k))?;
+                v.print(pp)
             })?,
         };
         Ok(())
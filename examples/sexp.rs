@@ -14,17 +14,19 @@ impl SExp {
     pub fn print<R: Render>(&self, pp: &mut Printer<R>) -> Result<(), R::Error> {
         match self {
             SExp::Atom(x) => pp.text(format!("{}", x))?,
-            SExp::List(xs) => pp.cgroup(1, |pp| {
+            SExp::List(xs) => {
                 pp.text("(")?;
-                if let Some((first, rest)) = xs.split_first() {
-                    first.print(pp)?;
-                    for v in rest {
-                        pp.space()?;
-                        v.print(pp)?;
+                pp.calign(0, |pp| {
+                    if let Some((first, rest)) = xs.split_first() {
+                        first.print(pp)?;
+                        for v in rest {
+                            pp.space()?;
+                            v.print(pp)?;
+                        }
                     }
-                }
-                pp.text(")")
-            })?,
+                    pp.text(")")
+                })?
+            }
         }
         Ok(())
     }
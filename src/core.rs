@@ -6,11 +6,18 @@
 use std::{
     borrow::Cow,
     collections::VecDeque,
+    iter, mem,
     ops::{AddAssign, Sub},
+    vec,
 };
 
-use crate::render::Render;
+use crate::{
+    render::Render,
+    source_map::{Location, SourceMap, Span},
+    width::{WidthMeasure, WidthStrategy},
+};
 
+/// A position in the input stream, in the units of the width measure.
 #[derive(Clone, Copy)]
 struct Position(pub usize);
 
@@ -29,44 +36,227 @@ impl Sub<Position> for Position {
     }
 }
 
-enum Token<'a> {
+enum Token<'a, A> {
     Text(Cow<'a, str>),
-    Break { indent: usize },
-    Group(OutGroup<'a>),
+    Conditional {
+        text: Cow<'a, str>,
+        width: usize,
+        on_break: bool,
+    },
+    Break {
+        indent: usize,
+        text: Option<Box<BreakText<'a>>>,
+    },
+    Newline,
+    BlockBegin,
+    BlockEnd,
+    PushPrefix {
+        text: Cow<'a, str>,
+        width: usize,
+    },
+    PopPrefix,
+    PushAnnotation(A),
+    PopAnnotation,
+    Mark(usize),
+    SpanBegin(usize),
+    SpanEnd,
+    Cursor(usize),
+    Group(OutGroup<'a, A>),
+}
+
+struct BreakText<'a> {
+    flat: Cow<'a, str>,
+    pre: Cow<'a, str>,
+    post: Cow<'a, str>,
+    pre_width: usize,
+    post_width: usize,
 }
 
-struct OutGroup<'a> {
-    tokens: Vec<(Token<'a>, usize)>,
+struct OutGroup<'a, A> {
+    tokens: Vec<(Token<'a, A>, usize)>,
+    /// The number of tokens in the group, including the nested groups.
+    len: usize,
     consistent: bool,
+    align: bool,
+}
+
+impl<A> Drop for OutGroup<'_, A> {
+    fn drop(&mut self) {
+        // Flatten the nested groups, so that dropping them does not recurse.
+        let mut tokens = mem::take(&mut self.tokens);
+        while let Some((token, _)) = tokens.pop() {
+            if let Token::Group(mut group) = token {
+                tokens.append(&mut group.tokens);
+            }
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
-enum RenderFrame {
+struct RenderFrame {
+    /// The column that the indents of the breaks in the group are relative to.
+    base: usize,
+    /// The part of `base` that comes from alignment rather than indentation.
+    align: usize,
+    layout: Layout,
+}
+
+#[derive(Clone, Copy)]
+enum Layout {
     Fits,
     Break { consistent: bool },
 }
 
+impl Default for RenderFrame {
+    fn default() -> Self {
+        Self {
+            base: 0,
+            align: 0,
+            layout: Layout::Break { consistent: false },
+        }
+    }
+}
+
+/// The characters used to write indentation.
+///
+/// Indents are always counted in columns. With tabs, the indent is written
+/// with tabs up to the last tab stop it reaches, and padded with spaces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndentStyle {
+    /// Indent with spaces.
+    #[default]
+    Spaces,
+    /// Indent with tabs of the given width.
+    Tabs(usize),
+    /// Indent with tabs of the given width, but align with spaces.
+    ///
+    /// The indent of an aligned group (see [`Printer::calign`]) is written
+    /// with tabs up to the indent of the line where the group starts, and
+    /// with spaces after that.
+    SmartTabs(usize),
+}
+
+/// What to do with indents deeper than a limit.
+///
+/// Without a limit, the lines of a deeply nested document drift off to the
+/// right, and once the indent exceeds the line width, nothing fits anymore.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum IndentOverflow<'a> {
+    /// Indent as deep as needed.
+    #[default]
+    Extend,
+    /// Indent at most the given number of columns.
+    Cap(usize),
+    /// Indents deeper than `max` columns wrap back to the start of the line,
+    /// and the line starts with `marker`.
+    Wrap { max: usize, marker: Cow<'a, str> },
+}
+
+/// Statistics about the elements buffered by a [`Printer`].
+///
+/// Elements are buffered until the printer can decide whether the groups
+/// they belong to fit on the line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferStats {
+    /// The number of elements buffered now.
+    pub tokens: usize,
+    /// The number of groups whose layout is not decided yet.
+    pub groups: usize,
+    /// The largest number of elements buffered at once.
+    pub peak_tokens: usize,
+}
+
 /// The `Printer` is a pretty printing engine. It takes a sequence of layout elements and
 /// produces a pretty printed representation of the elements.
-pub struct Printer<'a, R: Render = String> {
+///
+/// Whitespace at the end of a line is never written, whether it comes from
+/// breaks, indentation, prefixes or text elements. Only the lines of verbatim
+/// text (see [`Printer::verbatim`]) are kept as they are.
+pub struct Printer<'a, R: Render = String, M = WidthStrategy> {
     // common
     line_width: usize,
+    ribbon_width: usize,
+    measure: M,
+    indent_style: IndentStyle,
+    indent_overflow: IndentOverflow<'a>,
+    newline: Cow<'a, str>,
+    max_blank_lines: Option<usize>,
+    max_buffered: usize,
 
     // scanner
     position: Position,
+    /// Indents relative to the innermost aligned group.
     indent: Vec<isize>,
-    dq: VecDeque<(Position, OutGroup<'a>)>,
+    dq: VecDeque<(Position, OutGroup<'a, R::Annotation>)>,
+    /// The number of tokens in `dq`.
+    buffered: usize,
+    peak_buffered: usize,
+    /// Whether no text or soft break has been scanned since the last hard
+    /// break.
+    scan_line_start: bool,
 
     // renderer
     renderer: R,
-    remaining: usize,
+    column: usize,
     render_stack: Vec<RenderFrame>,
     pending_indent: usize,
+    /// The indent of the current line, not counting the prefixes.
+    line_indent: usize,
+    /// Whitespace that is not written until some text follows it on the
+    /// same line, so that lines never end with whitespace.
+    pending_space: String,
+    /// The part of the indent of the current line that can be written with tabs.
+    line_tabs: usize,
+    prefixes: Vec<(Cow<'a, str>, usize)>,
+    /// Annotations that are not passed to the renderer until some text is
+    /// written in their region.
+    pending_annotations: Vec<R::Annotation>,
+    /// The line of the output, counted from zero.
+    line: usize,
+    /// The number of bytes written to the renderer.
+    offset: usize,
+    /// The location after the last text written.
+    text_end: Location,
+    /// Marks and starts of spans that are not located until some text is
+    /// written.
+    pending_marks: Vec<usize>,
+    pending_starts: Vec<usize>,
+    /// Cursors in whitespace that is not written yet.
+    pending_cursors: Vec<usize>,
+    open_spans: Vec<(usize, Location)>,
+    source_map: SourceMap,
+    /// Total width of the prefixes of the current line.
+    line_prefix: usize,
+    /// Whether the prefixes of the current line are not written yet. They are
+    /// written when some text or a line break follows them.
+    pending_prefixes: bool,
+    /// Whether nothing but prefixes has been written on the current line.
+    line_start: bool,
+    /// Whether nothing but whitespace has been written on the current line
+    /// since it was started by a break, or since the start of the output.
+    line_blank: bool,
+    /// The length of the whitespace at the end of the prefixes of the current
+    /// line, kept at the start of `pending_space`.
+    prefix_space: usize,
+    /// Blank lines that are not written until some text follows them.
+    pending_lines: usize,
+    /// The maximum number of pending blank lines written at the boundary of a
+    /// nested block, until some text is written.
+    blank_cap: Option<usize>,
+    /// The `blank_cap`, the pending blank lines and the line where each open
+    /// nested block begins.
+    blocks: Vec<(Option<usize>, usize, usize)>,
+    /// Whether the next line break on the current blank line is not counted,
+    /// because the line was started in a nested block that has ended.
+    skip_break: bool,
 }
 
 impl<'a, R: Render> Printer<'a, R> {
     /// Create a new printer.
     ///
+    /// The line width is in the units of the width measure, see
+    /// [`Printer::with_measure`].
+    ///
     /// # Panics
     ///
     /// If line width is not between 1 and 65536.
@@ -78,23 +268,288 @@ impl<'a, R: Render> Printer<'a, R> {
         );
         let mut pp = Self {
             line_width,
+            ribbon_width: line_width,
+            measure: WidthStrategy::default(),
+            indent_style: IndentStyle::default(),
+            indent_overflow: IndentOverflow::default(),
+            newline: Cow::Borrowed("\n"),
+            max_blank_lines: None,
+            max_buffered: usize::MAX,
             position: Position(0),
             indent: vec![0],
             dq: VecDeque::new(),
+            buffered: 0,
+            peak_buffered: 0,
+            scan_line_start: true,
             renderer,
-            remaining: line_width,
+            column: 0,
             render_stack: Vec::new(),
             pending_indent: 0,
+            line_indent: 0,
+            pending_space: String::new(),
+            line_tabs: 0,
+            prefixes: Vec::new(),
+            pending_annotations: Vec::new(),
+            line: 0,
+            offset: 0,
+            text_end: Location::default(),
+            pending_marks: Vec::new(),
+            pending_starts: Vec::new(),
+            pending_cursors: Vec::new(),
+            open_spans: Vec::new(),
+            source_map: SourceMap::default(),
+            line_prefix: 0,
+            pending_prefixes: true,
+            line_start: true,
+            line_blank: true,
+            prefix_space: 0,
+            pending_lines: 0,
+            // The output is the outermost block.
+            blank_cap: Some(0),
+            blocks: Vec::new(),
+            skip_break: false,
         };
         pp.scan_begin(0, false);
         pp
     }
+}
+
+impl<'a, R: Render, M: WidthMeasure> Printer<'a, R, M> {
+    /// Set the ribbon width, the maximum width of a line without its
+    /// indentation and prefixes.
+    ///
+    /// Groups are broken if they do not fit in either the line width or the
+    /// ribbon width, so that deeply indented lines do not get too long.
+    /// Defaults to the line width.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 40).with_ribbon_width(10);
+    /// pp.nest(20, |pp| {
+    ///     pp.hard_break()?;
+    ///     pp.cgroup(0, |pp| {
+    ///         pp.text("foo")?;
+    ///         pp.space()?;
+    ///         pp.text("bar_baz")
+    ///     })
+    /// })?;
+    /// assert_eq!(pp.finish()?, format!("\n{0}foo\n{0}bar_baz", " ".repeat(20)));
+    /// # Ok::<(), ()>(())
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the ribbon width is zero.
+    pub fn with_ribbon_width(mut self, ribbon_width: usize) -> Self {
+        assert!(ribbon_width > 0, "ribbon width must be positive");
+        self.ribbon_width = ribbon_width;
+        self
+    }
+
+    /// Set the measure used for text elements.
+    ///
+    /// Defaults to [`WidthStrategy::Columns`]. Breaks and indents are written
+    /// as one space per unit of the measure, so it should measure a space as
+    /// one unit.
+    pub fn with_measure<N: WidthMeasure>(self, measure: N) -> Printer<'a, R, N> {
+        Printer {
+            line_width: self.line_width,
+            ribbon_width: self.ribbon_width,
+            measure,
+            indent_style: self.indent_style,
+            indent_overflow: self.indent_overflow,
+            newline: self.newline,
+            max_blank_lines: self.max_blank_lines,
+            max_buffered: self.max_buffered,
+            position: self.position,
+            indent: self.indent,
+            dq: self.dq,
+            buffered: self.buffered,
+            peak_buffered: self.peak_buffered,
+            scan_line_start: self.scan_line_start,
+            renderer: self.renderer,
+            column: self.column,
+            render_stack: self.render_stack,
+            pending_indent: self.pending_indent,
+            line_indent: self.line_indent,
+            pending_space: self.pending_space,
+            line_tabs: self.line_tabs,
+            prefixes: self.prefixes,
+            pending_annotations: self.pending_annotations,
+            line: self.line,
+            offset: self.offset,
+            text_end: self.text_end,
+            pending_marks: self.pending_marks,
+            pending_starts: self.pending_starts,
+            pending_cursors: self.pending_cursors,
+            open_spans: self.open_spans,
+            source_map: self.source_map,
+            line_prefix: self.line_prefix,
+            pending_prefixes: self.pending_prefixes,
+            line_start: self.line_start,
+            line_blank: self.line_blank,
+            prefix_space: self.prefix_space,
+            pending_lines: self.pending_lines,
+            blank_cap: self.blank_cap,
+            blocks: self.blocks,
+            skip_break: self.skip_break,
+        }
+    }
+
+    /// Set the characters used to write indentation.
+    ///
+    /// Defaults to [`IndentStyle::Spaces`].
+    ///
+    /// # Panics
+    ///
+    /// If the tab width is zero.
+    pub fn with_indent_style(mut self, indent_style: IndentStyle) -> Self {
+        if let IndentStyle::Tabs(width) | IndentStyle::SmartTabs(width) = indent_style {
+            assert!(width > 0, "tab width must be positive");
+        }
+        self.indent_style = indent_style;
+        self
+    }
+
+    /// Set how indents deeper than a limit are written.
+    ///
+    /// Defaults to [`IndentOverflow::Extend`].
+    ///
+    /// ```
+    /// # use elegance::{IndentOverflow, Printer};
+    /// let overflow = IndentOverflow::Wrap { max: 4, marker: "> ".into() };
+    /// let mut pp = Printer::new(String::new(), 40).with_indent_overflow(overflow);
+    /// pp.text("a")?;
+    /// pp.nest(3, |pp| {
+    ///     pp.hard_break()?;
+    ///     pp.text("b")?;
+    ///     pp.nest(3, |pp| {
+    ///         pp.hard_break()?;
+    ///         pp.text("c")
+    ///     })
+    /// })?;
+    /// assert_eq!(pp.finish()?, "a\n   b\n>   c");
+    /// # Ok::<(), ()>(())
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the limit of [`IndentOverflow::Wrap`] is zero.
+    pub fn with_indent_overflow(mut self, indent_overflow: IndentOverflow<'a>) -> Self {
+        if let IndentOverflow::Wrap { max, .. } = indent_overflow {
+            assert!(max > 0, "indent limit must be positive");
+        }
+        self.indent_overflow = indent_overflow;
+        self
+    }
+
+    /// Set the sequence written for line breaks, such as `"\r\n"`.
+    ///
+    /// It is used for all the line breaks, including hard breaks and the
+    /// newlines in multi-line text. Defaults to `"\n"`.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 40).with_newline("\r\n");
+    /// pp.text("foo\nbar")?;
+    /// pp.hard_break()?;
+    /// assert_eq!(pp.finish()?, "foo\r\nbar\r\n");
+    /// # Ok::<(), ()>(())
+    /// ```
+    pub fn with_newline(mut self, newline: impl Into<Cow<'a, str>>) -> Self {
+        self.newline = newline.into();
+        self
+    }
+
+    /// Limit the number of blank lines in a row.
+    ///
+    /// Consecutive line breaks are merged so that at most `max` blank lines
+    /// are written in a row. Blank lines at the start or end of a nested block
+    /// (see [`Printer::scan_nest_begin`]) and at the start or end of the
+    /// document are dropped. The line breaks in verbatim text are kept as they
+    /// are.
+    ///
+    /// By default, every line break starts a new line.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 40).with_max_blank_lines(1);
+    /// pp.text("{")?;
+    /// pp.nest(4, |pp| {
+    ///     pp.blank_line()?;
+    ///     pp.text("foo")?;
+    ///     pp.blank_line()?;
+    ///     pp.blank_line()?;
+    ///     pp.text("bar")?;
+    ///     pp.blank_line()
+    /// })?;
+    /// pp.hard_break()?;
+    /// pp.text("}")?;
+    /// assert_eq!(pp.finish()?, "{\n    foo\n\n    bar\n}");
+    /// # Ok::<(), ()>(())
+    /// ```
+    pub fn with_max_blank_lines(mut self, max: usize) -> Self {
+        self.max_blank_lines = Some(max);
+        self
+    }
+
+    /// Limit the number of elements buffered by the printer.
+    ///
+    /// Usually, the elements of a group are buffered until either the group
+    /// is complete or it is known not to fit on the line, so the memory use
+    /// is bounded by the line width. Many small groups inside a wide group can
+    /// still buffer a lot of elements. Past the limit, the outermost pending
+    /// groups are broken, even if they would otherwise fit.
+    ///
+    /// By default, the number of elements is not limited.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 40).with_max_buffered(4);
+    /// pp.cgroup(0, |pp| {
+    ///     pp.text("a")?;
+    ///     pp.space()?;
+    ///     pp.text("b")?;
+    ///     pp.space()?;
+    ///     pp.text("c")
+    /// })?;
+    /// assert!(pp.buffer_stats().peak_tokens <= 4);
+    /// assert_eq!(pp.finish()?, "a\nb\nc");
+    /// # Ok::<(), ()>(())
+    /// ```
+    pub fn with_max_buffered(mut self, max: usize) -> Self {
+        self.max_buffered = max;
+        self
+    }
+
+    /// Statistics about the elements buffered by the printer.
+    pub fn buffer_stats(&self) -> BufferStats {
+        BufferStats {
+            tokens: self.buffered,
+            groups: self.dq.len(),
+            peak_tokens: self.peak_buffered,
+        }
+    }
 
     /// Maximum line width.
     pub const MAX_WIDTH: usize = 65536;
 
+    /// Measure the width of `text` with the measure of the printer.
+    #[inline]
+    pub fn measure(&self, text: &str) -> usize {
+        self.measure.measure(text)
+    }
+
     /// Write a text element.
+    ///
+    /// The text must not contain newlines. Use [`Printer::text`] or
+    /// [`Printer::verbatim`] to write multi-line text.
+    ///
+    /// # Panics
+    ///
+    /// If the text contains a newline.
     pub fn scan_text(&mut self, text: Cow<'a, str>, width: usize) -> Result<(), R::Error> {
+        assert!(!text.contains('\n'), "text must not contain newlines");
         self.scan(width, Token::Text(text))
     }
 
@@ -109,20 +564,212 @@ impl<'a, R: Render> Printer<'a, R> {
     ///
     /// If the total indent is negative.
     pub fn scan_break(&mut self, size: usize, indent: isize) -> Result<(), R::Error> {
-        let indent = (self.indent() + indent)
-            .try_into()
-            .expect("indent must >= 0");
-        self.scan(size, Token::Break { indent })
+        let indent = self.break_indent(indent);
+        self.scan(size, Token::Break { indent, text: None })
+    }
+
+    /// Write a break element with text.
+    ///
+    /// If there is enough space, the break is written as `flat`. Otherwise,
+    /// `pre` is written before the line break, and `post` is written after
+    /// the indent of the new line. The texts must not contain newlines.
+    ///
+    /// Whether the break fits is decided by the width of `flat` only. The
+    /// width of `pre` counts towards the line it ends, but it does not make
+    /// the earlier breaks of an inconsistent group break, so `pre` can
+    /// overflow the line, just like text after such a break.
+    ///
+    /// The indent is handled as in [`Printer::scan_break`].
+    ///
+    /// # Panics
+    ///
+    /// If the total indent is negative.
+    pub fn scan_break_text(
+        &mut self,
+        flat: Cow<'a, str>,
+        pre: Cow<'a, str>,
+        post: Cow<'a, str>,
+        indent: isize,
+    ) -> Result<(), R::Error> {
+        debug_assert!(
+            !(flat.contains('\n') || pre.contains('\n') || post.contains('\n')),
+            "text must not contain newlines"
+        );
+        let indent = self.break_indent(indent);
+        let size = self.measure(&flat);
+        let pre_width = self.measure(&pre);
+        let post_width = self.measure(&post);
+        let text = Box::new(BreakText {
+            flat,
+            pre,
+            post,
+            pre_width,
+            post_width,
+        });
+        self.scan(
+            size,
+            Token::Break {
+                indent,
+                text: Some(text),
+            },
+        )
+    }
+
+    /// Write a conditional text element.
+    ///
+    /// If `on_break` is true, the text is only written when the enclosing
+    /// group is broken; otherwise it is only written when the group fits on
+    /// one line. The text must not contain newlines.
+    pub fn scan_conditional(
+        &mut self,
+        text: Cow<'a, str>,
+        width: usize,
+        on_break: bool,
+    ) -> Result<(), R::Error> {
+        debug_assert!(!text.contains('\n'), "text must not contain newlines");
+        // Only the flat layout counts towards the width of the group.
+        let flat_width = if on_break { 0 } else { width };
+        self.scan(
+            flat_width,
+            Token::Conditional {
+                text,
+                width,
+                on_break,
+            },
+        )
+    }
+
+    /// Write a line break that always starts a new line at column zero,
+    /// regardless of the current indent.
+    ///
+    /// Like a hard break, it forces all the enclosing groups to break.
+    pub fn scan_verbatim_break(&mut self) -> Result<(), R::Error> {
+        self.scan(Self::MAX_WIDTH, Token::Newline)
+    }
+
+    /// Begin a nested block.
+    ///
+    /// The indent of the breaks until the matching [`Printer::scan_nest_end`]
+    /// is increased by `indent`, without creating a group.
+    pub fn scan_nest_begin(&mut self, indent: isize) -> Result<(), R::Error> {
+        self.indent.push(self.indent() + indent);
+        self.scan(0, Token::BlockBegin)
+    }
+
+    /// End a nested block.
+    pub fn scan_nest_end(&mut self) -> Result<(), R::Error> {
+        self.indent.pop();
+        self.scan(0, Token::BlockEnd)
+    }
+
+    /// Begin a prefixed block.
+    ///
+    /// Until the matching [`Printer::scan_prefix_end`], every new line starts
+    /// with `prefix`, written before the indent. Prefixes of nested blocks are
+    /// written one after another. If the block begins at the start of a line,
+    /// the prefix is also written on that line. If it ends at the start of a
+    /// line, that is, right after a line break, the prefix is not written on
+    /// that line.
+    ///
+    /// The enclosing groups only count the width of the prefix if the block
+    /// begins after a hard break or at the start of the output. A block that
+    /// begins right after a break which is laid out as a line break can
+    /// therefore make the rest of that line overflow by the prefix width.
+    ///
+    /// The prefix must not contain newlines.
+    pub fn scan_prefix_begin(
+        &mut self,
+        prefix: Cow<'a, str>,
+        width: usize,
+    ) -> Result<(), R::Error> {
+        debug_assert!(!prefix.contains('\n'), "prefix must not contain newlines");
+        // The prefix only takes up width if it is written on this line.
+        let size = if self.scan_line_start { width } else { 0 };
+        self.scan(
+            size,
+            Token::PushPrefix {
+                text: prefix,
+                width,
+            },
+        )
+    }
+
+    /// End a prefixed block.
+    pub fn scan_prefix_end(&mut self) -> Result<(), R::Error> {
+        self.scan(0, Token::PopPrefix)
+    }
+
+    /// Begin an annotated region.
+    ///
+    /// The text written until the matching [`Printer::scan_annotation_end`] is
+    /// passed to the renderer between [`Render::push_annotation`] and
+    /// [`Render::pop_annotation`]. Regions without any text are skipped, and
+    /// the annotations do not take up any width.
+    pub fn scan_annotation_begin(&mut self, annotation: R::Annotation) -> Result<(), R::Error> {
+        self.scan(0, Token::PushAnnotation(annotation))
+    }
+
+    /// End an annotated region.
+    pub fn scan_annotation_end(&mut self) -> Result<(), R::Error> {
+        self.scan(0, Token::PopAnnotation)
+    }
+
+    /// Write a mark.
+    ///
+    /// The mark is located at the start of the text that follows it, see
+    /// [`Printer::finish_with_source_map`].
+    pub fn scan_mark(&mut self, id: usize) -> Result<(), R::Error> {
+        self.scan(0, Token::Mark(id))
+    }
+
+    /// Begin a marked span.
+    ///
+    /// The span starts at the text that follows it, and ends after the text
+    /// that precedes the matching [`Printer::scan_span_end`]. The whitespace
+    /// around the text is not part of the span. A span without any text is
+    /// located like a mark.
+    pub fn scan_span_begin(&mut self, id: usize) -> Result<(), R::Error> {
+        self.scan(0, Token::SpanBegin(id))
+    }
+
+    /// End a marked span.
+    pub fn scan_span_end(&mut self) -> Result<(), R::Error> {
+        self.scan(0, Token::SpanEnd)
+    }
+
+    /// Write a cursor.
+    ///
+    /// Unlike a mark, the cursor is located right after the text that
+    /// precedes it. If it is in whitespace, it is located at the start of the
+    /// text that follows, or at the end of the text that precedes if the
+    /// whitespace ends the line. See [`Printer::finish_with_source_map`].
+    pub fn scan_cursor(&mut self, id: usize) -> Result<(), R::Error> {
+        self.scan(0, Token::Cursor(id))
     }
 
     /// Begin a group.
     pub fn scan_begin(&mut self, indent: isize, consistent: bool) {
         self.indent.push(self.indent() + indent);
+        self.begin(consistent, false);
+    }
+
+    /// Begin an aligned group.
+    ///
+    /// The indent of the group is relative to the column where the group
+    /// starts in the output, rather than the indent of the enclosing group.
+    pub fn scan_begin_align(&mut self, indent: isize, consistent: bool) {
+        self.indent.push(indent);
+        self.begin(consistent, true);
+    }
+
+    fn begin(&mut self, consistent: bool, align: bool) {
         self.dq.push_back((
             self.position,
             OutGroup {
                 tokens: Vec::with_capacity(12),
+                len: 0,
                 consistent,
+                align,
             },
         ));
     }
@@ -133,8 +780,12 @@ impl<'a, R: Render> Printer<'a, R> {
         if let Some((s, grp1)) = self.dq.pop_back() {
             let width = self.position - s;
             if let Some((_, grp2)) = self.dq.back_mut() {
+                grp2.len += grp1.len + 1;
                 grp2.tokens.push((Token::Group(grp1), width));
+                self.buffered += 1;
+                self.prune()?;
             } else {
+                self.buffered -= grp1.len;
                 self.render_begin(grp1, width)?;
                 self.render_end()?;
             }
@@ -144,25 +795,107 @@ impl<'a, R: Render> Printer<'a, R> {
         Ok(())
     }
 
+    /// Write out the elements printed so far, and flush the renderer.
+    ///
+    /// The groups that are not complete yet are broken, as if they did not
+    /// fit on the line, and the complete groups inside them are laid out as
+    /// usual. Printing can continue afterwards. Whitespace at the end of the
+    /// current line is still held back until some text follows it.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 40);
+    /// pp.cgroup(2, |pp| {
+    ///     pp.text("foo")?;
+    ///     pp.space()?;
+    ///     pp.flush()?;
+    ///     pp.text("bar")
+    /// })?;
+    /// assert_eq!(pp.finish()?, "foo\n  bar");
+    /// # Ok::<(), ()>(())
+    /// ```
+    pub fn flush(&mut self) -> Result<(), R::Error> {
+        while let Some((_, grp)) = self.dq.pop_front() {
+            self.buffered -= grp.len;
+            self.render_begin(grp, Self::MAX_WIDTH)?;
+        }
+        self.renderer.flush()
+    }
+
     /// Finish the printer and return the result.
     ///
     /// # Panics
     ///
     /// If there is an unclosed group.
     pub fn finish(mut self) -> Result<R, R::Error> {
+        self.finish_output()?;
+        Ok(self.renderer)
+    }
+
+    /// Finish the printer and return the result, with the spans of the marked
+    /// elements in the output.
+    ///
+    /// See [`Printer::mark`] and [`Printer::span`].
+    ///
+    /// # Panics
+    ///
+    /// If there is an unclosed group.
+    pub fn finish_with_source_map(mut self) -> Result<(R, SourceMap), R::Error> {
+        self.finish_output()?;
+        Ok((self.renderer, self.source_map))
+    }
+
+    fn finish_output(&mut self) -> Result<(), R::Error> {
         self.scan_end()?;
         assert!(self.dq.is_empty(), "unclosed group");
-        Ok(self.renderer)
+        // The whitespace at the end of the last line is dropped, and the marks
+        // and cursors at the end are located at the end of the output.
+        self.place_cursors(self.text_end);
+        let end = if self.text_end.line == self.line {
+            self.text_end
+        } else {
+            let column = if self.pending_prefixes {
+                0
+            } else {
+                let space = &self.pending_space[..self.prefix_space];
+                self.line_prefix.saturating_sub(self.measure(space))
+            };
+            Location {
+                line: self.line,
+                column,
+                offset: self.offset,
+            }
+        };
+        self.pending_space.clear();
+        self.resolve_marks(end);
+        Ok(())
     }
 
     fn indent(&self) -> isize {
         *self.indent.last().unwrap()
     }
 
-    fn scan(&mut self, width: usize, out: Token<'a>) -> Result<(), R::Error> {
+    fn break_indent(&self, indent: isize) -> usize {
+        (self.indent() + indent)
+            .try_into()
+            .expect("indent must >= 0")
+    }
+
+    fn scan(&mut self, width: usize, out: Token<'a, R::Annotation>) -> Result<(), R::Error> {
         self.position += width;
+        match &out {
+            Token::Text(_) => self.scan_line_start = false,
+            // Only hard breaks are known to start a new line.
+            Token::Break { text, .. } => {
+                self.scan_line_start = text.is_none() && width >= Self::MAX_WIDTH
+            }
+            Token::Newline => self.scan_line_start = true,
+            _ => {}
+        }
         if let Some((_, grp)) = self.dq.back_mut() {
             grp.tokens.push((out, width));
+            grp.len += 1;
+            self.buffered += 1;
             self.prune()?;
         } else {
             self.render_token(out, width)?;
@@ -171,21 +904,143 @@ impl<'a, R: Render> Printer<'a, R> {
     }
 
     fn prune(&mut self) -> Result<(), R::Error> {
-        while self
-            .dq
-            .front()
-            .is_some_and(|&(s, _)| self.position - s > self.remaining)
-        {
+        while self.dq.front().is_some_and(|&(s, _)| {
+            self.position - s > self.remaining() || self.buffered > self.max_buffered
+        }) {
             let (_, grp) = self.dq.pop_front().unwrap();
+            self.buffered -= grp.len;
             self.render_begin(grp, Self::MAX_WIDTH)?;
         }
+        self.peak_buffered = self.peak_buffered.max(self.buffered);
         Ok(())
     }
 
-    fn render_token(&mut self, token: Token<'a>, width: usize) -> Result<(), R::Error> {
+    fn render_token(
+        &mut self,
+        token: Token<'a, R::Annotation>,
+        width: usize,
+    ) -> Result<(), R::Error> {
         match token {
-            Token::Text(text) => self.render_text(text, width),
-            Token::Break { indent } => self.render_break(indent, width),
+            Token::Text(text) => self.render_text(&text, width),
+            Token::Conditional {
+                text,
+                width,
+                on_break,
+            } => {
+                let broken = matches!(self.frame().layout, Layout::Break { .. });
+                if broken == on_break {
+                    self.render_text(&text, width)?;
+                }
+                Ok(())
+            }
+            Token::Break { indent, text } => self.render_break(indent, text, width),
+            Token::Newline => {
+                self.place_cursors(self.text_end);
+                // Verbatim line breaks are never merged, and the whitespace
+                // before them is kept, unless it is only from the prefixes.
+                self.render_lines()?;
+                if self.pending_space.len() > self.prefix_space {
+                    self.flush_space()?;
+                }
+                self.write_newline()?;
+                self.line_blank = false;
+                self.start_line(0, 0);
+                Ok(())
+            }
+            Token::BlockBegin => {
+                let lines = if self.line_blank {
+                    self.pending_lines
+                } else {
+                    0
+                };
+                self.blocks.push((self.blank_cap, lines, self.line));
+                self.blank_cap = Some(self.blank_cap.map_or(lines, |cap| cap.min(lines)));
+                Ok(())
+            }
+            Token::BlockEnd => {
+                let (cap, lines, line) = self.blocks.pop().unwrap_or_default();
+                // The blank lines started in the block are dropped. If the
+                // block ends with a line break, that line break also ends the
+                // line of the enclosing block.
+                if self.line_blank && self.line > line {
+                    self.pending_lines = 0;
+                    self.skip_break = true;
+                } else {
+                    self.pending_lines = self.pending_lines.min(lines);
+                }
+                // No text in the block, so the cap of the enclosing block is
+                // still in effect.
+                if self.blank_cap.is_some() {
+                    self.blank_cap = cap;
+                }
+                Ok(())
+            }
+            Token::PushPrefix { text, width } => {
+                if self.line_start {
+                    self.render_lines()?;
+                    if !self.pending_prefixes {
+                        self.write_prefix(&text)?;
+                    }
+                    self.line_prefix += width;
+                    self.column += width;
+                } else {
+                    // The prefix is not written on the line where the block
+                    // begins in the middle.
+                    self.render_prefixes()?;
+                }
+                self.prefixes.push((text, width));
+                Ok(())
+            }
+            Token::PopPrefix => {
+                if self.line_start && self.pending_prefixes {
+                    // The line starts after the end of the block.
+                    if let Some((_, width)) = self.prefixes.last() {
+                        self.line_prefix -= width;
+                        self.column -= width;
+                    }
+                } else {
+                    self.render_prefixes()?;
+                }
+                self.prefixes.pop();
+                Ok(())
+            }
+            Token::PushAnnotation(annotation) => {
+                self.pending_annotations.push(annotation);
+                Ok(())
+            }
+            Token::PopAnnotation => {
+                if self.pending_annotations.pop().is_none() {
+                    self.renderer.pop_annotation()?;
+                }
+                Ok(())
+            }
+            Token::Mark(id) => {
+                self.pending_marks.push(id);
+                Ok(())
+            }
+            Token::SpanBegin(id) => {
+                self.pending_starts.push(id);
+                Ok(())
+            }
+            Token::SpanEnd => {
+                if let Some(id) = self.pending_starts.pop() {
+                    // The span is empty, so it is located at the next text.
+                    self.pending_marks.push(id);
+                } else if let Some((id, start)) = self.open_spans.pop() {
+                    let end = self.text_end;
+                    self.source_map.insert(id, Span { start, end });
+                }
+                Ok(())
+            }
+            Token::Cursor(id) => {
+                if self.pending_space.is_empty() && self.pending_indent == 0 && !self.line_start {
+                    let location = self.location();
+                    self.source_map.insert(id, Span::at(location));
+                } else {
+                    self.pending_cursors.push(id);
+                }
+                Ok(())
+            }
             Token::Group(group) => {
                 self.render_begin(group, width)?;
                 self.render_end()
@@ -193,53 +1048,345 @@ impl<'a, R: Render> Printer<'a, R> {
         }
     }
 
-    fn render_text(&mut self, text: Cow<'a, str>, width: usize) -> Result<(), R::Error> {
-        if self.pending_indent > 0 {
-            self.renderer.write_spaces(self.pending_indent)?;
-            self.pending_indent = 0;
+    fn render_text(&mut self, text: &str, width: usize) -> Result<(), R::Error> {
+        let trimmed = text.trim_end_matches([' ', '\t']);
+        if !trimmed.is_empty() {
+            if self.line_blank {
+                self.line_blank = false;
+                self.render_lines()?;
+            }
+            self.blank_cap = None;
+        }
+        self.render_indent();
+        self.line_start = false;
+        if trimmed.is_empty() {
+            self.write_str(text)?;
+        } else {
+            self.flush_space()?;
+            for annotation in self.pending_annotations.drain(..) {
+                self.renderer.push_annotation(annotation)?;
+            }
+            self.resolve_marks(self.location());
+            self.write_str(text)?;
+            let trailing = &text[trimmed.len()..];
+            let trailing_width = if trailing.is_empty() {
+                0
+            } else {
+                self.measure(trailing)
+            };
+            self.text_end = Location {
+                line: self.line,
+                column: (self.column + width).saturating_sub(trailing_width),
+                offset: self.offset,
+            };
         }
-        self.renderer.write_str(&text)?;
-        self.remaining = self.remaining.saturating_sub(width);
+        self.column += width;
         Ok(())
     }
 
-    fn render_break(&mut self, indent: usize, width: usize) -> Result<(), R::Error> {
-        let frame = self
-            .render_stack
-            .last()
-            .copied()
-            .unwrap_or(RenderFrame::Break { consistent: false });
-        let fits = match frame {
-            RenderFrame::Fits => true,
-            RenderFrame::Break { consistent, .. } => !consistent && width < self.remaining,
+    /// Write `text`, holding back the whitespace at its end.
+    fn write_str(&mut self, text: &str) -> Result<(), R::Error> {
+        let trimmed = text.trim_end_matches([' ', '\t']);
+        if !trimmed.is_empty() {
+            self.flush_space()?;
+            self.renderer.write_str(trimmed)?;
+            self.offset += trimmed.len();
+        }
+        self.pending_space.push_str(&text[trimmed.len()..]);
+        Ok(())
+    }
+
+    /// Write a prefix like [`Printer::write_str`], outside of the annotated
+    /// regions.
+    fn write_prefix(&mut self, prefix: &str) -> Result<(), R::Error> {
+        let trimmed = prefix.trim_end_matches([' ', '\t']);
+        if !trimmed.is_empty() {
+            self.flush_space()?;
+            self.renderer.write_prefix(trimmed)?;
+            self.offset += trimmed.len();
+        }
+        self.pending_space.push_str(&prefix[trimmed.len()..]);
+        Ok(())
+    }
+
+    /// Locate the pending marks, starts of spans and cursors at `location`.
+    fn resolve_marks(&mut self, location: Location) {
+        for id in self.pending_starts.drain(..) {
+            self.open_spans.push((id, location));
+        }
+        for id in self.pending_marks.drain(..) {
+            self.source_map.insert(id, Span::at(location));
+        }
+        self.place_cursors(location);
+    }
+
+    fn place_cursors(&mut self, location: Location) {
+        for id in self.pending_cursors.drain(..) {
+            self.source_map.insert(id, Span::at(location));
+        }
+    }
+
+    fn location(&self) -> Location {
+        Location {
+            line: self.line,
+            column: self.column,
+            offset: self.offset,
+        }
+    }
+
+    fn flush_space(&mut self) -> Result<(), R::Error> {
+        self.render_prefixes()?;
+        self.offset += self.pending_space.len();
+        let mut space = self.pending_space.as_str();
+        while !space.is_empty() {
+            let tabs = space.len() - space.trim_start_matches('\t').len();
+            if tabs > 0 {
+                self.renderer.write_str(&space[..tabs])?;
+            }
+            space = &space[tabs..];
+            let spaces = space.len() - space.trim_start_matches(' ').len();
+            if spaces > 0 {
+                self.renderer.write_spaces(spaces)?;
+            }
+            space = &space[spaces..];
+        }
+        self.pending_space.clear();
+        Ok(())
+    }
+
+    fn render_break(
+        &mut self,
+        indent: usize,
+        text: Option<Box<BreakText<'a>>>,
+        width: usize,
+    ) -> Result<(), R::Error> {
+        let frame = self.frame();
+        let fits = match frame.layout {
+            Layout::Fits => true,
+            Layout::Break { consistent } => !consistent && width < self.remaining(),
         };
         if fits {
-            self.renderer.write_spaces(width)?;
-            self.remaining = self.remaining.saturating_sub(width);
+            match text {
+                Some(text) => self.render_text(&text.flat, width)?,
+                None => {
+                    self.render_indent();
+                    self.pending_space.extend(iter::repeat(' ').take(width));
+                    self.column += width;
+                    self.line_start = false;
+                }
+            }
         } else {
-            self.renderer.write_str("\n")?;
-            self.pending_indent = indent;
-            self.remaining = self.line_width.saturating_sub(indent);
+            if let Some(text) = &text {
+                if !text.pre.is_empty() {
+                    self.render_text(&text.pre, text.pre_width)?;
+                }
+            }
+            let indent = frame.base + indent;
+            let tabs = if frame.align == 0 {
+                indent
+            } else {
+                frame.base - frame.align
+            };
+            let (indent, wrapped) = match self.indent_overflow {
+                IndentOverflow::Cap(max) if indent > max => (max, false),
+                IndentOverflow::Wrap { max, .. } if indent > max => (indent % max, true),
+                _ => (indent, false),
+            };
+            self.render_newline(indent, tabs.min(indent))?;
+            if wrapped {
+                self.render_marker()?;
+            }
+            if let Some(text) = text {
+                if !text.post.is_empty() {
+                    self.render_text(&text.post, text.post_width)?;
+                }
+            }
         }
         Ok(())
     }
 
-    fn render_begin(&mut self, group: OutGroup<'a>, width: usize) -> Result<(), R::Error> {
-        self.render_stack.push(if width <= self.remaining {
-            RenderFrame::Fits
+    fn render_newline(&mut self, indent: usize, tabs: usize) -> Result<(), R::Error> {
+        // The whitespace at the end of the line is dropped, so the cursors in
+        // it are moved back to the text.
+        self.place_cursors(self.text_end);
+        if self.line_blank && self.max_blank_lines.is_some() {
+            if !mem::take(&mut self.skip_break) {
+                self.pending_lines += 1;
+            }
+            self.pending_space.truncate(self.prefix_space);
         } else {
-            RenderFrame::Break {
-                consistent: group.consistent,
+            self.write_newline()?;
+            self.line_blank = true;
+            self.skip_break = false;
+        }
+        self.start_line(indent, tabs);
+        Ok(())
+    }
+
+    /// Write the marker of a line with a wrapped indent.
+    fn render_marker(&mut self) -> Result<(), R::Error> {
+        self.line_blank = false;
+        self.render_lines()?;
+        let overflow = mem::take(&mut self.indent_overflow);
+        if let IndentOverflow::Wrap { marker, .. } = &overflow {
+            let width = self.measure(marker);
+            self.write_prefix(marker)?;
+            // The marker is indented over like a prefix.
+            self.line_prefix += width;
+            self.column += width;
+        }
+        self.indent_overflow = overflow;
+        Ok(())
+    }
+
+    fn start_line(&mut self, indent: usize, tabs: usize) {
+        self.pending_indent = indent;
+        self.line_indent = indent;
+        self.line_tabs = tabs;
+        self.column = self.line_prefix + indent;
+        self.line_start = true;
+    }
+
+    /// Write the pending blank lines before the current line.
+    fn render_lines(&mut self) -> Result<(), R::Error> {
+        let lines = mem::take(&mut self.pending_lines);
+        if lines == 0 {
+            return Ok(());
+        }
+        let max = self.max_blank_lines.unwrap_or(usize::MAX);
+        let lines = lines.min(max).min(self.blank_cap.unwrap_or(max));
+        let space = mem::take(&mut self.pending_space);
+        for _ in 0..lines {
+            self.write_newline()?;
+        }
+        self.pending_space = space;
+        Ok(())
+    }
+
+    fn write_newline(&mut self) -> Result<(), R::Error> {
+        // An empty line still gets its prefixes.
+        self.pending_space.clear();
+        self.render_prefixes()?;
+        self.pending_space.clear();
+        self.renderer.write_newline(&self.newline)?;
+        self.line += 1;
+        self.offset += self.newline.len();
+        self.line_prefix = self.prefixes.iter().map(|(_, width)| width).sum();
+        self.pending_prefixes = true;
+        self.prefix_space = 0;
+        Ok(())
+    }
+
+    /// Write the prefixes of the current line, if they are not written yet.
+    fn render_prefixes(&mut self) -> Result<(), R::Error> {
+        if !mem::take(&mut self.pending_prefixes) {
+            return Ok(());
+        }
+        let space = mem::take(&mut self.pending_space);
+        let prefixes = mem::take(&mut self.prefixes);
+        for (prefix, _) in &prefixes {
+            self.write_prefix(prefix)?;
+        }
+        self.prefixes = prefixes;
+        self.prefix_space = self.pending_space.len();
+        self.pending_space.push_str(&space);
+        Ok(())
+    }
+
+    fn render_indent(&mut self) {
+        if self.pending_indent == 0 {
+            return;
+        }
+        let indent = mem::take(&mut self.pending_indent);
+        let (tab_width, tabs) = match self.indent_style {
+            IndentStyle::Spaces => {
+                self.pending_space.extend(iter::repeat(' ').take(indent));
+                return;
+            }
+            IndentStyle::Tabs(width) => (width, indent),
+            IndentStyle::SmartTabs(width) => (width, self.line_tabs.min(indent)),
+        };
+        // Tab stops are counted from the start of the line, not the prefix.
+        let start = self.line_prefix;
+        let end = self.line_prefix + indent;
+        let stops = (start + tabs) / tab_width - start / tab_width;
+        let column = if stops > 0 {
+            (start + tabs) / tab_width * tab_width
+        } else {
+            start
+        };
+        self.pending_space.extend(iter::repeat('\t').take(stops));
+        self.pending_space
+            .extend(iter::repeat(' ').take(end - column));
+    }
+
+    /// Begin a group and render its tokens.
+    ///
+    /// The nested groups are rendered with an explicit stack rather than by
+    /// recursion, so that the nesting depth is only limited by memory.
+    fn render_begin(
+        &mut self,
+        group: OutGroup<'a, R::Annotation>,
+        width: usize,
+    ) -> Result<(), R::Error> {
+        let mut stack = vec![self.push_frame(group, width)];
+        while let Some(tokens) = stack.last_mut() {
+            match tokens.next() {
+                Some((Token::Group(group), width)) => {
+                    let tokens = self.push_frame(group, width);
+                    stack.push(tokens);
+                }
+                Some((token, width)) => self.render_token(token, width)?,
+                None => {
+                    stack.pop();
+                    // The outermost group is ended by the caller.
+                    if !stack.is_empty() {
+                        self.render_end()?;
+                    }
+                }
             }
-        });
-        for (out, width) in group.tokens {
-            self.render_token(out, width)?;
         }
         Ok(())
     }
 
+    fn push_frame(
+        &mut self,
+        mut group: OutGroup<'a, R::Annotation>,
+        width: usize,
+    ) -> vec::IntoIter<(Token<'a, R::Annotation>, usize)> {
+        let (base, align) = if group.align {
+            let base = self.column - self.line_prefix;
+            (base, base.saturating_sub(self.line_tabs))
+        } else {
+            let frame = self.frame();
+            (frame.base, frame.align)
+        };
+        let layout = if width <= self.remaining() {
+            Layout::Fits
+        } else {
+            Layout::Break {
+                consistent: group.consistent,
+            }
+        };
+        self.render_stack.push(RenderFrame {
+            base,
+            align,
+            layout,
+        });
+        mem::take(&mut group.tokens).into_iter()
+    }
+
     fn render_end(&mut self) -> Result<(), R::Error> {
         self.render_stack.pop();
         Ok(())
     }
+
+    fn frame(&self) -> RenderFrame {
+        self.render_stack.last().copied().unwrap_or_default()
+    }
+
+    fn remaining(&self) -> usize {
+        let ribbon_end = self.line_prefix + self.line_indent + self.ribbon_width;
+        self.line_width.min(ribbon_end).saturating_sub(self.column)
+    }
 }
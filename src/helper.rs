@@ -1,24 +1,265 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, iter};
 
-use crate::{core::Printer, render::Render};
+use crate::{core::Printer, render::Render, width::WidthMeasure};
 
-impl<'a, R: Render> Printer<'a, R> {
+/// The style of a delimited list, see [`Printer::delimited_with`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListStyle<'a> {
+    /// Whether the list is broken consistently, with each item on its own
+    /// line, or inconsistently, with as many items as possible on each line.
+    /// Either way, the delimiters are written on their own lines.
+    ///
+    /// Defaults to `true`.
+    pub consistent: bool,
+    /// Whether a separator is written after the last item when the list is
+    /// broken.
+    ///
+    /// Defaults to `false`.
+    pub trailing: bool,
+    /// The text written for an empty list. If `None`, the delimiters are
+    /// written next to each other.
+    ///
+    /// Defaults to `None`.
+    pub empty: Option<Cow<'a, str>>,
+}
+
+impl Default for ListStyle<'_> {
+    fn default() -> Self {
+        Self {
+            consistent: true,
+            trailing: false,
+            empty: None,
+        }
+    }
+}
+
+/// Whether `c` is a space that words can be broken at.
+fn is_breaking_space(c: char) -> bool {
+    c.is_whitespace() && !matches!(c, '\u{a0}' | '\u{2007}' | '\u{202f}')
+}
+
+/// Split prose into words, with whether each word follows a blank line.
+fn split_words(text: &str) -> impl Iterator<Item = (bool, &str)> {
+    let mut rest = text;
+    iter::from_fn(move || {
+        let start = rest.find(|c| !is_breaking_space(c))?;
+        let blank = rest[..start].matches('\n').nth(1).is_some();
+        let end = rest[start..]
+            .find(is_breaking_space)
+            .map_or(rest.len(), |end| start + end);
+        let word = &rest[start..end];
+        rest = &rest[end..];
+        Some((blank, word))
+    })
+}
+
+fn split_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+}
+
+impl<'a, R: Render, M: WidthMeasure> Printer<'a, R, M> {
     /// Write a text element.
     ///
+    /// The width of the text is measured with the measure set by
+    /// [`Printer::with_measure`].
+    ///
+    /// If the text contains newlines, each of them is written as a hard line
+    /// break, and the following line is indented at the current indent.
+    ///
     /// ```
     /// # use elegance::Printer;
     /// let mut pp = Printer::new(String::new(), 40);
     /// pp.text("Hello, world!")?;
-    /// assert_eq!(pp.finish()?, "Hello, world!");
+    /// pp.cgroup(2, |pp| pp.text("\nfoo\nbar"))?;
+    /// assert_eq!(pp.finish()?, "Hello, world!\n  foo\n  bar");
     /// # Ok::<(), ()>(())
     /// ```
     #[inline]
     pub fn text(&mut self, text: impl Into<Cow<'a, str>>) -> Result<(), R::Error> {
         let text = text.into();
-        let width = text.len();
+        if text.contains('\n') {
+            return self.multiline(text, false);
+        }
+        let width = self.measure(&text);
         self.scan_text(text, width)
     }
 
+    /// Write a multi-line text element verbatim.
+    ///
+    /// Unlike [`Printer::text`], the lines after a newline are not indented,
+    /// so the text is written exactly as is, including the whitespace at the
+    /// end of its lines. The newlines still force all the enclosing groups to
+    /// break.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 40);
+    /// pp.cgroup(2, |pp| {
+    ///     pp.text("let s = \"")?;
+    ///     pp.verbatim("foo\nbar")?;
+    ///     pp.text("\";")
+    /// })?;
+    /// assert_eq!(pp.finish()?, "let s = \"foo\nbar\";");
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn verbatim(&mut self, text: impl Into<Cow<'a, str>>) -> Result<(), R::Error> {
+        self.multiline(text.into(), true)
+    }
+
+    fn multiline(&mut self, text: Cow<'a, str>, verbatim: bool) -> Result<(), R::Error> {
+        match text {
+            Cow::Borrowed(text) => {
+                for (i, line) in split_lines(text).enumerate() {
+                    self.line(i > 0, Cow::Borrowed(line), verbatim)?;
+                }
+            }
+            Cow::Owned(text) => {
+                for (i, line) in split_lines(&text).enumerate() {
+                    self.line(i > 0, Cow::Owned(line.to_owned()), verbatim)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, newline: bool, line: Cow<'a, str>, verbatim: bool) -> Result<(), R::Error> {
+        if newline {
+            if verbatim {
+                self.scan_verbatim_break()?;
+            } else {
+                self.hard_break()?;
+            }
+        }
+        if !line.is_empty() {
+            let width = self.measure(&line);
+            self.scan_text(line, width)?;
+        }
+        Ok(())
+    }
+
+    /// Write a text element that only appears when the enclosing group is
+    /// broken.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 10);
+    /// for n in [3, 6] {
+    ///     pp.group(|pp| {
+    ///         pp.text("[")?;
+    ///         pp.nest(2, |pp| {
+    ///             for i in 0..n {
+    ///                 pp.zero_break()?;
+    ///                 pp.text(i.to_string())?;
+    ///                 if i + 1 < n {
+    ///                     pp.text(",")?;
+    ///                 }
+    ///             }
+    ///             pp.if_break(",")
+    ///         })?;
+    ///         pp.zero_break()?;
+    ///         pp.text("]")
+    ///     })?;
+    ///     pp.hard_break()?;
+    /// }
+    /// assert_eq!(pp.finish()?, "[0,1,2]\n[\n  0,\n  1,\n  2,\n  3,\n  4,\n  5,\n]\n");
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn if_break(&mut self, text: impl Into<Cow<'a, str>>) -> Result<(), R::Error> {
+        let text = text.into();
+        let width = self.measure(&text);
+        self.scan_conditional(text, width, true)
+    }
+
+    /// Write a text element that only appears when the enclosing group fits
+    /// on one line.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 10);
+    /// for s in ["foo", "foobar"] {
+    ///     pp.cgroup(0, |pp| {
+    ///         pp.text(s)?;
+    ///         pp.if_flat(" |")?;
+    ///         pp.space()?;
+    ///         pp.text("bar")
+    ///     })?;
+    ///     pp.hard_break()?;
+    /// }
+    /// assert_eq!(pp.finish()?, "foo | bar\nfoobar\nbar\n");
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn if_flat(&mut self, text: impl Into<Cow<'a, str>>) -> Result<(), R::Error> {
+        let text = text.into();
+        let width = self.measure(&text);
+        self.scan_conditional(text, width, false)
+    }
+
+    /// Write prose that is reflowed to the line width.
+    ///
+    /// The text is split into words at whitespace, and the words are filled
+    /// onto as few lines as possible, like [`Printer::fill`]. Non-breaking
+    /// spaces do not split words, and punctuation stays attached to the words.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 20);
+    /// pp.text("// ")?;
+    /// pp.nest(3, |pp| pp.words("See  https://example.com/foo for\nthe 10\u{a0}kB limit."))?;
+    /// assert_eq!(pp.finish()?, "// See\n   https://example.com/foo\n   for the 10\u{a0}kB\n   limit.");
+    /// # Ok::<(), ()>(())
+    /// ```
+    pub fn words(&mut self, text: impl Into<Cow<'a, str>>) -> Result<(), R::Error> {
+        self.prose(text.into(), false)
+    }
+
+    /// Write paragraphs of prose that are reflowed to the line width.
+    ///
+    /// Like [`Printer::words`], but paragraphs separated by blank lines in the
+    /// text are kept separated by a blank line.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 20);
+    /// pp.paragraph("Lorem ipsum dolor sit amet,\nconsectetur.\n\n  Sed do eiusmod.")?;
+    /// assert_eq!(pp.finish()?, "Lorem ipsum dolor\nsit amet,\nconsectetur.\n\nSed do eiusmod.");
+    /// # Ok::<(), ()>(())
+    /// ```
+    pub fn paragraph(&mut self, text: impl Into<Cow<'a, str>>) -> Result<(), R::Error> {
+        self.prose(text.into(), true)
+    }
+
+    fn prose(&mut self, text: Cow<'a, str>, paragraphs: bool) -> Result<(), R::Error> {
+        match text {
+            Cow::Borrowed(text) => self.write_words(
+                split_words(text).map(|(blank, word)| (blank, Cow::Borrowed(word))),
+                paragraphs,
+            ),
+            Cow::Owned(text) => self.write_words(
+                split_words(&text).map(|(blank, word)| (blank, Cow::Owned(word.to_owned()))),
+                paragraphs,
+            ),
+        }
+    }
+
+    fn write_words(
+        &mut self,
+        words: impl Iterator<Item = (bool, Cow<'a, str>)>,
+        paragraphs: bool,
+    ) -> Result<(), R::Error> {
+        for (i, (blank, word)) in words.enumerate() {
+            let new_paragraph = i > 0 && paragraphs && blank;
+            if new_paragraph {
+                self.blank_line()?;
+            }
+            self.fill_item(i == 0 || new_paragraph, |pp| pp.text(word))?;
+        }
+        Ok(())
+    }
+
     /// Write a hard line break.
     ///
     /// ```
@@ -35,6 +276,25 @@ impl<'a, R: Render> Printer<'a, R> {
         self.scan_break(Self::MAX_WIDTH, 0)
     }
 
+    /// Write a hard line break followed by an empty line.
+    ///
+    /// See [`Printer::with_max_blank_lines`] to merge consecutive blank lines.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 40);
+    /// pp.text("Hello,")?;
+    /// pp.blank_line()?;
+    /// pp.text("world!")?;
+    /// assert_eq!(pp.finish()?, "Hello,\n\nworld!");
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn blank_line(&mut self) -> Result<(), R::Error> {
+        self.hard_break()?;
+        self.hard_break()
+    }
+
     /// Write a zero-width line break.
     ///
     /// ```
@@ -83,34 +343,252 @@ impl<'a, R: Render> Printer<'a, R> {
         self.scan_break(1, 0)
     }
 
+    /// Write a break with text.
+    ///
+    /// If there is enough space, the break is written as `flat`. Otherwise,
+    /// `pre` is written at the end of the line and `post` at the start of
+    /// the next line, after the indent.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 20);
+    /// pp.cgroup(2, |pp| {
+    ///     pp.text("foo")?;
+    ///     pp.text_break(", ", ",", "")?;
+    ///     pp.text("bar")
+    /// })?;
+    /// pp.hard_break()?;
+    /// pp.cgroup(2, |pp| {
+    ///     pp.text("echo")?;
+    ///     pp.text_break(" ", " \\", "")?;
+    ///     pp.text("x".repeat(20))
+    /// })?;
+    /// assert_eq!(pp.finish()?, "foo, bar\necho \\\n  xxxxxxxxxxxxxxxxxxxx");
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn text_break(
+        &mut self,
+        flat: impl Into<Cow<'a, str>>,
+        pre: impl Into<Cow<'a, str>>,
+        post: impl Into<Cow<'a, str>>,
+    ) -> Result<(), R::Error> {
+        self.scan_break_text(flat.into(), pre.into(), post.into(), 0)
+    }
+
+    /// Start every line written in `f` with a prefix.
+    ///
+    /// The prefix is written after each line break, before the indent, and
+    /// the text is wrapped to the width left after the prefix. Prefixes of
+    /// nested calls are written one after another.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 20);
+    /// pp.with_prefix("/// ", |pp| {
+    ///     pp.words("Lorem ipsum dolor sit amet.")?;
+    ///     pp.hard_break()?;
+    ///     pp.with_prefix("> ", |pp| pp.words("Sed do eiusmod tempor."))
+    /// })?;
+    /// assert_eq!(
+    ///     pp.finish()?,
+    ///     "/// Lorem ipsum\n/// dolor sit amet.\n/// > Sed do eiusmod\n/// > tempor."
+    /// );
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn with_prefix(
+        &mut self,
+        prefix: impl Into<Cow<'a, str>>,
+        f: impl FnOnce(&mut Self) -> Result<(), R::Error>,
+    ) -> Result<(), R::Error> {
+        let prefix = prefix.into();
+        let width = self.measure(&prefix);
+        self.scan_prefix_begin(prefix, width)?;
+        f(self)?;
+        self.scan_prefix_end()
+    }
+
+    /// Annotate the text written in `f`.
+    ///
+    /// The renderer receives the annotation with [`Render::push_annotation`]
+    /// before the text, and [`Render::pop_annotation`] after it. Annotations
+    /// do not change the layout.
+    ///
+    /// ```
+    /// # use elegance::{Printer, Render};
+    /// struct Markup(String, Vec<&'static str>);
+    ///
+    /// impl Render for Markup {
+    ///     type Error = ();
+    ///     type Annotation = &'static str;
+    ///
+    ///     fn write_str(&mut self, s: &str) -> Result<(), ()> {
+    ///         self.0.push_str(s);
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn push_annotation(&mut self, tag: &'static str) -> Result<(), ()> {
+    ///         self.0 += &format!("<{tag}>");
+    ///         self.1.push(tag);
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn pop_annotation(&mut self) -> Result<(), ()> {
+    ///         self.0 += &format!("</{}>", self.1.pop().unwrap());
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let mut pp = Printer::new(Markup(String::new(), Vec::new()), 40);
+    /// pp.annotate("kw", |pp| pp.text("let"))?;
+    /// pp.space()?;
+    /// pp.text("x = ")?;
+    /// pp.annotate("num", |pp| pp.text("1"))?;
+    /// assert_eq!(pp.finish()?.0, "<kw>let</kw> x = <num>1</num>");
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn annotate(
+        &mut self,
+        annotation: R::Annotation,
+        f: impl FnOnce(&mut Self) -> Result<(), R::Error>,
+    ) -> Result<(), R::Error> {
+        self.scan_annotation_begin(annotation)?;
+        f(self)?;
+        self.scan_annotation_end()
+    }
+
+    /// Mark the position of the text that follows.
+    ///
+    /// The location of the mark is reported by
+    /// [`Printer::finish_with_source_map`], as an empty span.
+    #[inline]
+    pub fn mark(&mut self, id: usize) -> Result<(), R::Error> {
+        self.scan_mark(id)
+    }
+
+    /// Write a cursor, such as the cursor of an editor.
+    ///
+    /// The cursor stays attached to the text before it, even if the
+    /// whitespace after the text is changed or removed. Its location is
+    /// reported by [`Printer::finish_with_source_map`], as an empty span.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 10);
+    /// pp.cgroup(2, |pp| {
+    ///     pp.text("foo(bar,")?;
+    ///     pp.cursor(0)?;
+    ///     pp.space()?;
+    ///     pp.cursor(1)?;
+    ///     pp.text("baz)")
+    /// })?;
+    /// let (output, source_map) = pp.finish_with_source_map()?;
+    /// assert_eq!(output, "foo(bar,\n  baz)");
+    /// assert_eq!(source_map.get(0).unwrap().start.offset, 8);
+    /// assert_eq!(source_map.get(1).unwrap().start.offset, 11);
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn cursor(&mut self, id: usize) -> Result<(), R::Error> {
+        self.scan_cursor(id)
+    }
+
+    /// Mark the span of the text written in `f`.
+    ///
+    /// The span is reported by [`Printer::finish_with_source_map`]. It does
+    /// not include the whitespace before the first text and after the last
+    /// one.
+    ///
+    /// ```
+    /// # use elegance::{Location, Printer};
+    /// let mut pp = Printer::new(String::new(), 10);
+    /// pp.cgroup(2, |pp| {
+    ///     pp.text("foo(")?;
+    ///     pp.zero_break()?;
+    ///     pp.span(1, |pp| pp.text("bar + baz"))?;
+    ///     pp.text(")")
+    /// })?;
+    /// let (output, source_map) = pp.finish_with_source_map()?;
+    /// assert_eq!(output, "foo(\n  bar + baz)");
+    /// let span = source_map.get(1).unwrap();
+    /// assert_eq!(span.start, Location { line: 1, column: 2, offset: 7 });
+    /// assert_eq!(span.end, Location { line: 1, column: 11, offset: 16 });
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn span(
+        &mut self,
+        id: usize,
+        f: impl FnOnce(&mut Self) -> Result<(), R::Error>,
+    ) -> Result<(), R::Error> {
+        self.scan_span_begin(id)?;
+        f(self)?;
+        self.scan_span_end()
+    }
+
     /// Write a group.
     ///
-    /// The `consistent` parameter controls whether the group is
-    /// rendered with consistent indentation or not. See [`Printer::cgroup`]
-    /// and [`Printer::igroup`] for details.
+    /// The group is rendered consistently, without changing the indent. Either
+    /// all the breaks in the group are written on one line, or all of them are
+    /// line breaks. Use [`Printer::nest`] for indentation.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 12);
+    /// pp.group(|pp| {
+    ///     pp.text("{")?;
+    ///     pp.nest(2, |pp| {
+    ///         pp.space()?;
+    ///         pp.text("Hello,")?;
+    ///         pp.space()?;
+    ///         pp.text("world!")
+    ///     })?;
+    ///     pp.space()?;
+    ///     pp.text("}")
+    /// })?;
+    /// assert_eq!(pp.finish()?, "{\n  Hello,\n  world!\n}");
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn group(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<(), R::Error>,
+    ) -> Result<(), R::Error> {
+        self.scan_begin(0, true);
+        f(self)?;
+        self.scan_end()
+    }
+
+    /// Increase the indent of the breaks written in `f`.
+    ///
+    /// Unlike the indent of [`Printer::cgroup`] and [`Printer::igroup`], it
+    /// does not create a group, so the breaks still belong to the enclosing
+    /// group.
     ///
     /// ```
     /// # use elegance::Printer;
     /// let mut pp = Printer::new(String::new(), 40);
-    /// pp.group(2, true, |pp| {
-    ///     pp.text("Hello,")?;
+    /// pp.text("foo")?;
+    /// pp.nest(2, |pp| {
     ///     pp.hard_break()?;
-    ///     pp.text("world!")?;
-    ///     Ok(())
+    ///     pp.text("bar")
     /// })?;
-    /// assert_eq!(pp.finish()?, "Hello,\n  world!");
+    /// pp.hard_break()?;
+    /// pp.text("baz")?;
+    /// assert_eq!(pp.finish()?, "foo\n  bar\nbaz");
     /// # Ok::<(), ()>(())
     /// ```
     #[inline]
-    pub fn group(
+    pub fn nest(
         &mut self,
         indent: isize,
-        consistent: bool,
         f: impl FnOnce(&mut Self) -> Result<(), R::Error>,
     ) -> Result<(), R::Error> {
-        self.scan_begin(indent, consistent);
+        self.scan_nest_begin(indent)?;
         f(self)?;
-        self.scan_end()
+        self.scan_nest_end()
     }
 
     /// Write a consistent indented group.
@@ -138,7 +616,9 @@ impl<'a, R: Render> Printer<'a, R> {
         indent: isize,
         f: impl FnOnce(&mut Self) -> Result<(), R::Error>,
     ) -> Result<(), R::Error> {
-        self.group(indent, true, f)
+        self.scan_begin(indent, true);
+        f(self)?;
+        self.scan_end()
     }
 
     /// Write an inconsistent indented group.
@@ -166,6 +646,243 @@ impl<'a, R: Render> Printer<'a, R> {
         indent: isize,
         f: impl FnOnce(&mut Self) -> Result<(), R::Error>,
     ) -> Result<(), R::Error> {
-        self.group(indent, false, f)
+        self.scan_begin(indent, false);
+        f(self)?;
+        self.scan_end()
+    }
+
+    /// Write a consistent group aligned to the current column.
+    ///
+    /// The indent of the group is relative to the column where the group
+    /// starts, instead of the indent of the enclosing group.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 20);
+    /// pp.text("(define (foo ")?;
+    /// pp.calign(0, |pp| {
+    ///     pp.text("a")?;
+    ///     for arg in ["bar", "baz", "qux)"] {
+    ///         pp.space()?;
+    ///         pp.text(arg)?;
+    ///     }
+    ///     Ok(())
+    /// })?;
+    /// assert_eq!(pp.finish()?, "(define (foo a\n             bar\n             baz\n             qux)");
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn calign(
+        &mut self,
+        indent: isize,
+        f: impl FnOnce(&mut Self) -> Result<(), R::Error>,
+    ) -> Result<(), R::Error> {
+        self.scan_begin_align(indent, true);
+        f(self)?;
+        self.scan_end()
+    }
+
+    /// Write an inconsistent group aligned to the current column.
+    ///
+    /// See [`Printer::calign`] and [`Printer::igroup`] for details.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 20);
+    /// pp.text("foo(")?;
+    /// pp.ialign(0, |pp| {
+    ///     pp.text("aaaa,")?;
+    ///     for arg in ["bbbb,", "cccc,", "dddd)"] {
+    ///         pp.space()?;
+    ///         pp.text(arg)?;
+    ///     }
+    ///     Ok(())
+    /// })?;
+    /// assert_eq!(pp.finish()?, "foo(aaaa, bbbb, cccc,\n    dddd)");
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn ialign(
+        &mut self,
+        indent: isize,
+        f: impl FnOnce(&mut Self) -> Result<(), R::Error>,
+    ) -> Result<(), R::Error> {
+        self.scan_begin_align(indent, false);
+        f(self)?;
+        self.scan_end()
+    }
+
+    /// Write items separated by `sep` followed by a space.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 9);
+    /// pp.igroup(0, |pp| pp.intersperse(1..=6, ",", |pp, i| pp.text(i.to_string())))?;
+    /// assert_eq!(pp.finish()?, "1, 2, 3,\n4, 5, 6");
+    /// # Ok::<(), ()>(())
+    /// ```
+    pub fn intersperse<T>(
+        &mut self,
+        items: impl IntoIterator<Item = T>,
+        sep: impl Into<Cow<'a, str>>,
+        mut f: impl FnMut(&mut Self, T) -> Result<(), R::Error>,
+    ) -> Result<(), R::Error> {
+        let sep = sep.into();
+        let mut items = items.into_iter();
+        if let Some(first) = items.next() {
+            f(self, first)?;
+            for item in items {
+                self.text(sep.clone())?;
+                self.space()?;
+                f(self, item)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill items separated by `sep` onto as few lines as possible.
+    ///
+    /// Unlike [`Printer::intersperse`] in an inconsistent group, the space
+    /// before each item is broken if the item, together with its separator,
+    /// does not fit on the rest of the line. Each item is written in its own
+    /// consistent group.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 20);
+    /// let items = ["aaaa", "bbbbbbbbbb", "cc", "dddddddddddd"];
+    /// pp.fill(items, ",", |pp, s| pp.text(s))?;
+    /// assert_eq!(pp.finish()?, "aaaa, bbbbbbbbbb,\ncc, dddddddddddd");
+    /// # Ok::<(), ()>(())
+    /// ```
+    pub fn fill<T>(
+        &mut self,
+        items: impl IntoIterator<Item = T>,
+        sep: impl Into<Cow<'a, str>>,
+        mut f: impl FnMut(&mut Self, T) -> Result<(), R::Error>,
+    ) -> Result<(), R::Error> {
+        let sep = sep.into();
+        let mut items = items.into_iter().peekable();
+        let mut first = true;
+        while let Some(item) = items.next() {
+            let last = items.peek().is_none();
+            self.fill_item(first, |pp| {
+                f(pp, item)?;
+                if !last {
+                    pp.text(sep.clone())?;
+                }
+                Ok(())
+            })?;
+            first = false;
+        }
+        Ok(())
+    }
+
+    /// Write an item of [`Printer::fill`], preceded by a space unless it is
+    /// the first one.
+    fn fill_item(
+        &mut self,
+        first: bool,
+        f: impl FnOnce(&mut Self) -> Result<(), R::Error>,
+    ) -> Result<(), R::Error> {
+        // The space is broken if the following item does not fit.
+        self.cgroup(0, |pp| {
+            if !first {
+                pp.space()?;
+            }
+            pp.cgroup(0, f)
+        })
+    }
+
+    /// Write a list of items enclosed in delimiters.
+    ///
+    /// The items are separated by `sep` followed by a space. If the list does
+    /// not fit on one line, each item is written on its own line, indented by
+    /// `indent`, and the closing delimiter is written on a separate line.
+    ///
+    /// ```
+    /// # use elegance::Printer;
+    /// let mut pp = Printer::new(String::new(), 10);
+    /// pp.delimited("[", "]", 2, 1..=3, ",", |pp, i| pp.text(i.to_string()))?;
+    /// pp.hard_break()?;
+    /// pp.delimited("[", "]", 2, 1..=4, ",", |pp, i| pp.text(i.to_string()))?;
+    /// assert_eq!(pp.finish()?, "[1, 2, 3]\n[\n  1,\n  2,\n  3,\n  4\n]");
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[inline]
+    pub fn delimited<T>(
+        &mut self,
+        open: impl Into<Cow<'a, str>>,
+        close: impl Into<Cow<'a, str>>,
+        indent: isize,
+        items: impl IntoIterator<Item = T>,
+        sep: impl Into<Cow<'a, str>>,
+        f: impl FnMut(&mut Self, T) -> Result<(), R::Error>,
+    ) -> Result<(), R::Error> {
+        self.delimited_with(ListStyle::default(), open, close, indent, items, sep, f)
+    }
+
+    /// Write a list of items enclosed in delimiters, with the given style.
+    ///
+    /// See [`Printer::delimited`] and [`ListStyle`] for details.
+    ///
+    /// ```
+    /// # use elegance::{ListStyle, Printer};
+    /// let mut pp = Printer::new(String::new(), 10);
+    /// let style = ListStyle {
+    ///     trailing: true,
+    ///     empty: Some("[ ]".into()),
+    ///     ..Default::default()
+    /// };
+    /// for n in [0, 3, 4] {
+    ///     pp.delimited_with(style.clone(), "[", "]", 2, 1..=n, ",", |pp, i| {
+    ///         pp.text(i.to_string())
+    ///     })?;
+    ///     pp.hard_break()?;
+    /// }
+    /// assert_eq!(pp.finish()?, "[ ]\n[1, 2, 3]\n[\n  1,\n  2,\n  3,\n  4,\n]\n");
+    /// # Ok::<(), ()>(())
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub fn delimited_with<T>(
+        &mut self,
+        style: ListStyle<'a>,
+        open: impl Into<Cow<'a, str>>,
+        close: impl Into<Cow<'a, str>>,
+        indent: isize,
+        items: impl IntoIterator<Item = T>,
+        sep: impl Into<Cow<'a, str>>,
+        f: impl FnMut(&mut Self, T) -> Result<(), R::Error>,
+    ) -> Result<(), R::Error> {
+        let mut items = items.into_iter().peekable();
+        if items.peek().is_none() {
+            return match style.empty {
+                Some(empty) => self.text(empty),
+                None => {
+                    self.text(open)?;
+                    self.text(close)
+                }
+            };
+        }
+        let sep = sep.into();
+        // The delimiters and the trailing separator are broken consistently,
+        // even if the items are not.
+        self.cgroup(0, |pp| {
+            pp.text(open)?;
+            pp.nest(indent, |pp| {
+                pp.zero_break()?;
+                if style.consistent {
+                    pp.intersperse(items, sep.clone(), f)?;
+                } else {
+                    pp.igroup(0, |pp| pp.intersperse(items, sep.clone(), f))?;
+                }
+                if style.trailing {
+                    pp.if_break(sep)?;
+                }
+                Ok(())
+            })?;
+            pp.zero_break()?;
+            pp.text(close)
+        })
     }
 }
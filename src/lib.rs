@@ -3,6 +3,11 @@
 pub mod core;
 pub mod helper;
 pub mod render;
+pub mod source_map;
+pub mod width;
 
-pub use core::Printer;
+pub use core::{BufferStats, IndentOverflow, IndentStyle, Printer};
+pub use helper::ListStyle;
 pub use render::{Io, Render};
+pub use source_map::{Location, SourceMap, Span};
+pub use width::{WidthMeasure, WidthStrategy};
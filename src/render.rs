@@ -2,18 +2,62 @@
 
 use std::{ffi::OsString, io, iter};
 
+pub mod ansi;
+pub mod html;
+
 /// An output sink for printer.
 pub trait Render {
     type Error;
+    /// The annotations of the regions of the output, see [`Printer::annotate`].
+    ///
+    /// [`Printer::annotate`]: crate::Printer::annotate
+    type Annotation;
 
     fn write_str(&mut self, s: &str) -> Result<(), Self::Error>;
     fn write_spaces(&mut self, n: usize) -> Result<(), Self::Error> {
         self.write_str(&" ".repeat(n))
     }
+
+    /// Write a line break, with the sequence set by [`Printer::with_newline`].
+    ///
+    /// [`Printer::with_newline`]: crate::Printer::with_newline
+    fn write_newline(&mut self, newline: &str) -> Result<(), Self::Error> {
+        self.write_str(newline)
+    }
+
+    /// Write a line prefix, see [`Printer::with_prefix`].
+    ///
+    /// Prefixes are not part of the annotated regions, even when they are
+    /// written inside one.
+    ///
+    /// [`Printer::with_prefix`]: crate::Printer::with_prefix
+    fn write_prefix(&mut self, prefix: &str) -> Result<(), Self::Error> {
+        self.write_str(prefix)
+    }
+
+    /// Begin a region of the output with an annotation.
+    ///
+    /// Regions are nested, and each one is ended by a call to
+    /// [`Render::pop_annotation`]. They may span several lines.
+    fn push_annotation(&mut self, annotation: Self::Annotation) -> Result<(), Self::Error> {
+        let _ = annotation;
+        Ok(())
+    }
+
+    /// End the innermost annotated region.
+    fn pop_annotation(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Flush the output written so far, if it is buffered.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 impl Render for String {
     type Error = ();
+    type Annotation = ();
 
     fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
         self.push_str(s);
@@ -29,6 +73,7 @@ impl Render for String {
 
 impl Render for OsString {
     type Error = ();
+    type Annotation = ();
 
     fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
         self.push(s);
@@ -46,9 +91,14 @@ pub struct Io<W: io::Write>(pub W);
 
 impl<W: io::Write> Render for Io<W> {
     type Error = io::Error;
+    type Annotation = ();
 
     fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
         self.0.write_all(s.as_bytes())?;
         Ok(())
     }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush()
+    }
 }
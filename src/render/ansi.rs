@@ -0,0 +1,250 @@
+//! Renders text with ANSI escape sequences for terminals.
+
+use std::fmt::Write;
+
+use super::Render;
+
+/// A terminal color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+    /// A color of the 256-color palette.
+    Fixed(u8),
+    /// A 24-bit color.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Write the SGR parameters of the color, with `base` 30 for foreground
+    /// and 40 for background colors.
+    fn write_params(self, base: u8, params: &mut String) {
+        let code = match self {
+            Color::Black => 0,
+            Color::Red => 1,
+            Color::Green => 2,
+            Color::Yellow => 3,
+            Color::Blue => 4,
+            Color::Magenta => 5,
+            Color::Cyan => 6,
+            Color::White => 7,
+            Color::BrightBlack => 60,
+            Color::BrightRed => 61,
+            Color::BrightGreen => 62,
+            Color::BrightYellow => 63,
+            Color::BrightBlue => 64,
+            Color::BrightMagenta => 65,
+            Color::BrightCyan => 66,
+            Color::BrightWhite => 67,
+            Color::Fixed(n) => {
+                let _ = write!(params, "{};5;{n}", base + 8);
+                return;
+            }
+            Color::Rgb(r, g, b) => {
+                let _ = write!(params, "{};2;{r};{g};{b}", base + 8);
+                return;
+            }
+        };
+        let _ = write!(params, "{}", base + code);
+    }
+}
+
+/// The style of an annotated region of the output.
+///
+/// The style of a nested region is combined with the styles of the enclosing
+/// regions: the colors and link of the innermost region that sets them are
+/// used, and the attributes of all the regions are applied.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    /// The foreground color.
+    pub fg: Option<Color>,
+    /// The background color.
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub underline: bool,
+    /// The target of an OSC 8 hyperlink.
+    pub link: Option<String>,
+}
+
+impl Style {
+    fn merge(&self, inner: Style) -> Style {
+        Style {
+            fg: inner.fg.or(self.fg),
+            bg: inner.bg.or(self.bg),
+            bold: self.bold || inner.bold,
+            underline: self.underline || inner.underline,
+            link: inner.link.or_else(|| self.link.clone()),
+        }
+    }
+
+    fn write_start(&self, out: &mut String) {
+        let mut params = String::new();
+        if self.bold {
+            params.push_str("1;");
+        }
+        if self.underline {
+            params.push_str("4;");
+        }
+        if let Some(fg) = self.fg {
+            fg.write_params(30, &mut params);
+            params.push(';');
+        }
+        if let Some(bg) = self.bg {
+            bg.write_params(40, &mut params);
+            params.push(';');
+        }
+        if let Some(params) = params.strip_suffix(';') {
+            let _ = write!(out, "\x1b[{params}m");
+        }
+        if let Some(link) = &self.link {
+            let _ = write!(out, "\x1b]8;;{link}\x1b\\");
+        }
+    }
+
+    fn write_end(&self, out: &mut String) {
+        if self.fg.is_some() || self.bg.is_some() || self.bold || self.underline {
+            out.push_str("\x1b[0m");
+        }
+        if self.link.is_some() {
+            out.push_str("\x1b]8;;\x1b\\");
+        }
+    }
+}
+
+/// A renderer that styles the annotated regions with ANSI escape sequences.
+///
+/// The escape sequences do not count towards the width of the text. The
+/// styles are reset before each line break, and applied again after the
+/// prefixes and the indent of the next line, so that the output is displayed
+/// correctly line by line, such as by `less -R`.
+///
+/// ```
+/// # use elegance::Printer;
+/// # use elegance::render::ansi::{Ansi, Color, Style};
+/// let keyword = Style {
+///     fg: Some(Color::Blue),
+///     bold: true,
+///     ..Default::default()
+/// };
+/// let mut pp = Printer::new(Ansi::new(String::new()), 40);
+/// pp.annotate(keyword, |pp| pp.text("return"))?;
+/// pp.text(" x;")?;
+/// assert_eq!(pp.finish()?.into_inner(), "\x1b[1;34mreturn\x1b[0m x;");
+/// # Ok::<(), ()>(())
+/// ```
+pub struct Ansi<R> {
+    inner: R,
+    /// The combined styles of the annotated regions.
+    styles: Vec<Style>,
+    /// The style that is in effect in the output.
+    current: Option<Style>,
+}
+
+impl<R: Render> Ansi<R> {
+    /// Create a new renderer that writes to `inner`.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            styles: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Return the underlying renderer.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Switch the output to the style of the innermost region.
+    fn apply(&mut self) -> Result<(), R::Error> {
+        if self.current.as_ref() == self.styles.last() {
+            return Ok(());
+        }
+        let mut out = String::new();
+        if let Some(current) = self.current.take() {
+            current.write_end(&mut out);
+        }
+        if let Some(style) = self.styles.last() {
+            style.write_start(&mut out);
+            self.current = Some(style.clone());
+        }
+        self.inner.write_str(&out)
+    }
+
+    /// Reset the output to the default style.
+    fn reset(&mut self) -> Result<(), R::Error> {
+        if let Some(current) = self.current.take() {
+            let mut out = String::new();
+            current.write_end(&mut out);
+            self.inner.write_str(&out)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Render> Render for Ansi<R> {
+    type Error = R::Error;
+    type Annotation = Style;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        // Whitespace, like indentation, is not styled until the first text of
+        // the line.
+        if self.current.is_some() || !s.trim_start_matches([' ', '\t']).is_empty() {
+            self.apply()?;
+        }
+        self.inner.write_str(s)
+    }
+
+    fn write_spaces(&mut self, n: usize) -> Result<(), Self::Error> {
+        if self.current.is_some() {
+            self.apply()?;
+        }
+        self.inner.write_spaces(n)
+    }
+
+    fn write_newline(&mut self, newline: &str) -> Result<(), Self::Error> {
+        self.reset()?;
+        self.inner.write_newline(newline)
+    }
+
+    fn write_prefix(&mut self, prefix: &str) -> Result<(), Self::Error> {
+        // Prefixes are written in the default style.
+        self.reset()?;
+        self.inner.write_prefix(prefix)
+    }
+
+    fn push_annotation(&mut self, annotation: Style) -> Result<(), Self::Error> {
+        let style = match self.styles.last() {
+            Some(outer) => outer.merge(annotation),
+            None => annotation,
+        };
+        self.styles.push(style);
+        Ok(())
+    }
+
+    fn pop_annotation(&mut self) -> Result<(), Self::Error> {
+        self.styles.pop();
+        if self.styles.is_empty() {
+            self.reset()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
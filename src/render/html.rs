@@ -0,0 +1,143 @@
+//! Renders text as HTML.
+
+use std::borrow::Cow;
+
+use super::Render;
+
+/// A renderer that writes a `<pre>` block of HTML.
+///
+/// The text is escaped, and the annotated regions are written as `<span>`
+/// elements with the annotation as their class. The spans are closed at the
+/// end of each line and opened again on the next one, so every line has
+/// balanced tags. The width of the text is measured before escaping.
+///
+/// ```
+/// # use elegance::Printer;
+/// # use elegance::render::html::Html;
+/// let mut pp = Printer::new(Html::new(String::new()), 40);
+/// pp.annotate("kw".into(), |pp| pp.text("if"))?;
+/// pp.text(" a < b {}")?;
+/// assert_eq!(
+///     pp.finish()?.finish()?,
+///     "<pre><span class=\"kw\">if</span> a &lt; b {}</pre>"
+/// );
+/// # Ok::<(), ()>(())
+/// ```
+pub struct Html<R> {
+    inner: R,
+    /// The classes of the annotated regions.
+    classes: Vec<Cow<'static, str>>,
+    /// The number of regions whose spans are open in the output.
+    open: usize,
+    started: bool,
+}
+
+impl<R: Render> Html<R> {
+    /// Create a new renderer that writes to `inner`.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            classes: Vec::new(),
+            open: 0,
+            started: false,
+        }
+    }
+
+    /// Close the `<pre>` block and return the underlying renderer.
+    pub fn finish(mut self) -> Result<R, R::Error> {
+        self.start()?;
+        self.close_spans()?;
+        self.inner.write_str("</pre>")?;
+        Ok(self.inner)
+    }
+
+    fn start(&mut self) -> Result<(), R::Error> {
+        if !self.started {
+            self.started = true;
+            self.inner.write_str("<pre>")?;
+        }
+        Ok(())
+    }
+
+    fn open_spans(&mut self) -> Result<(), R::Error> {
+        while self.open < self.classes.len() {
+            self.inner.write_str("<span class=\"")?;
+            write_escaped(&mut self.inner, &self.classes[self.open])?;
+            self.inner.write_str("\">")?;
+            self.open += 1;
+        }
+        Ok(())
+    }
+
+    fn close_spans(&mut self) -> Result<(), R::Error> {
+        while self.open > 0 {
+            self.inner.write_str("</span>")?;
+            self.open -= 1;
+        }
+        Ok(())
+    }
+}
+
+fn write_escaped<R: Render>(inner: &mut R, mut s: &str) -> Result<(), R::Error> {
+    while let Some(i) = s.find(['&', '<', '>', '"', '\'']) {
+        inner.write_str(&s[..i])?;
+        inner.write_str(match s.as_bytes()[i] {
+            b'&' => "&amp;",
+            b'<' => "&lt;",
+            b'>' => "&gt;",
+            b'"' => "&quot;",
+            _ => "&#39;",
+        })?;
+        s = &s[i + 1..];
+    }
+    inner.write_str(s)
+}
+
+impl<R: Render> Render for Html<R> {
+    type Error = R::Error;
+    type Annotation = Cow<'static, str>;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        self.start()?;
+        // The spans are opened again after the prefixes and the indent.
+        if !s.trim_start_matches([' ', '\t']).is_empty() {
+            self.open_spans()?;
+        }
+        write_escaped(&mut self.inner, s)
+    }
+
+    fn write_spaces(&mut self, n: usize) -> Result<(), Self::Error> {
+        self.start()?;
+        self.inner.write_spaces(n)
+    }
+
+    fn write_newline(&mut self, newline: &str) -> Result<(), Self::Error> {
+        self.start()?;
+        self.close_spans()?;
+        self.inner.write_newline(newline)
+    }
+
+    fn write_prefix(&mut self, prefix: &str) -> Result<(), Self::Error> {
+        self.start()?;
+        self.close_spans()?;
+        write_escaped(&mut self.inner, prefix)
+    }
+
+    fn push_annotation(&mut self, annotation: Self::Annotation) -> Result<(), Self::Error> {
+        self.classes.push(annotation);
+        Ok(())
+    }
+
+    fn pop_annotation(&mut self) -> Result<(), Self::Error> {
+        if self.open == self.classes.len() && self.open > 0 {
+            self.inner.write_str("</span>")?;
+            self.open -= 1;
+        }
+        self.classes.pop();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
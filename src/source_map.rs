@@ -0,0 +1,70 @@
+//! Maps marked elements to their positions in the output.
+
+use std::collections::HashMap;
+
+/// A position in the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Location {
+    /// The line, counted from zero.
+    pub line: usize,
+    /// The column, in the units of the width measure of the printer.
+    pub column: usize,
+    /// The offset in bytes from the start of the output.
+    pub offset: usize,
+}
+
+/// A range of the output, from `start` inclusive to `end` exclusive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: Location,
+    pub end: Location,
+}
+
+impl Span {
+    pub(crate) fn at(location: Location) -> Self {
+        Self {
+            start: location,
+            end: location,
+        }
+    }
+}
+
+/// The spans of the marked elements in the output, see [`Printer::span`].
+///
+/// Marks and cursors are reported as empty spans.
+///
+/// [`Printer::span`]: crate::Printer::span
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    spans: HashMap<usize, Span>,
+}
+
+impl SourceMap {
+    /// The span of the element marked with `id`.
+    ///
+    /// If several elements are marked with the same id, the span of the last
+    /// one is returned.
+    pub fn get(&self, id: usize) -> Option<Span> {
+        self.spans.get(&id).copied()
+    }
+
+    /// The number of marked elements.
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Whether there is no marked element.
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Iterate over the ids and spans of the marked elements, in no
+    /// particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Span)> + '_ {
+        self.spans.iter().map(|(&id, &span)| (id, span))
+    }
+
+    pub(crate) fn insert(&mut self, id: usize, span: Span) {
+        self.spans.insert(id, span);
+    }
+}
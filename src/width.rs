@@ -0,0 +1,79 @@
+//! Measures the width of text.
+
+use unicode_width::UnicodeWidthStr;
+
+/// A metric for the width of text elements.
+///
+/// The printer calls the measure for every [`Printer::text`] element, and the
+/// whole layout is computed in the units it returns: the line width, break
+/// sizes and indentation are all counted in the same units. Breaks and
+/// indentation are written with one space per unit, so the measure should
+/// give a space the width of one unit. The built-in measures are provided by
+/// [`WidthStrategy`], and any `Fn(&str) -> usize` can be used as a custom one.
+///
+/// ```
+/// # use elegance::{Printer, WidthMeasure};
+/// // Uppercase letters are twice as wide as other characters.
+/// let font = |text: &str| {
+///     text.chars()
+///         .map(|c| if c.is_uppercase() { 2 } else { 1 })
+///         .sum()
+/// };
+/// assert_eq!(font.measure("Hello"), 6);
+///
+/// let mut pp = Printer::new(String::new(), 10).with_measure(font);
+/// pp.cgroup(0, |pp| {
+///     pp.text("HELLO")?;
+///     pp.space()?;
+///     pp.text("world")
+/// })?;
+/// assert_eq!(pp.finish()?, "HELLO\nworld");
+/// # Ok::<(), ()>(())
+/// ```
+///
+/// [`Printer::text`]: crate::Printer::text
+pub trait WidthMeasure {
+    /// Measure the width of `text`.
+    fn measure(&self, text: &str) -> usize;
+}
+
+impl<F: Fn(&str) -> usize> WidthMeasure for F {
+    #[inline]
+    fn measure(&self, text: &str) -> usize {
+        self(text)
+    }
+}
+
+/// The strategy used to measure the width of a text element.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WidthStrategy {
+    /// The length of the text in UTF-8 bytes.
+    Bytes,
+    /// The number of Unicode scalar values in the text.
+    Chars,
+    /// The number of terminal columns the text occupies.
+    ///
+    /// East Asian wide characters take two columns, combining marks and
+    /// zero-width joiners take none, and emoji sequences are measured as
+    /// a single grapheme cluster.
+    #[default]
+    Columns,
+}
+
+impl WidthMeasure for WidthStrategy {
+    /// ```
+    /// # use elegance::{WidthMeasure, WidthStrategy};
+    /// assert_eq!(WidthStrategy::Bytes.measure("日本"), 6);
+    /// assert_eq!(WidthStrategy::Chars.measure("日本"), 2);
+    /// assert_eq!(WidthStrategy::Columns.measure("日本"), 4);
+    /// ```
+    #[inline]
+    fn measure(&self, text: &str) -> usize {
+        match self {
+            WidthStrategy::Bytes => text.len(),
+            WidthStrategy::Chars => text.chars().count(),
+            WidthStrategy::Columns if text.is_ascii() => text.len(),
+            WidthStrategy::Columns => text.width(),
+        }
+    }
+}
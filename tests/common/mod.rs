@@ -0,0 +1,26 @@
+use elegance::Render;
+
+/// A renderer that writes the annotations as tags around the text.
+#[derive(Default)]
+pub struct Markup(pub String, pub Vec<&'static str>);
+
+impl Render for Markup {
+    type Error = ();
+    type Annotation = &'static str;
+
+    fn write_str(&mut self, s: &str) -> Result<(), ()> {
+        self.0.push_str(s);
+        Ok(())
+    }
+
+    fn push_annotation(&mut self, tag: &'static str) -> Result<(), ()> {
+        self.0 += &format!("<{tag}>");
+        self.1.push(tag);
+        Ok(())
+    }
+
+    fn pop_annotation(&mut self) -> Result<(), ()> {
+        self.0 += &format!("</{}>", self.1.pop().unwrap());
+        Ok(())
+    }
+}
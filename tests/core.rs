@@ -1,8 +1,20 @@
-use elegance::Printer;
+use elegance::{IndentOverflow, IndentStyle, ListStyle, Printer, Render};
+
+mod common;
+
+use common::Markup;
 
 #[track_caller]
 fn test_printer(f: impl FnOnce(&mut Printer) -> Result<(), ()>, expected: &str) {
-    let mut pp = Printer::new(String::new(), 40);
+    test_printer_with(Printer::new(String::new(), 40), f, expected);
+}
+
+#[track_caller]
+fn test_printer_with(
+    mut pp: Printer,
+    f: impl FnOnce(&mut Printer) -> Result<(), ()>,
+    expected: &str,
+) {
     f(&mut pp).unwrap();
     assert_eq!(pp.finish().unwrap(), expected);
 }
@@ -14,12 +26,26 @@ fn test_text() {
 
 #[test]
 fn test_space() {
-    test_printer(|pp| pp.space(), " ");
+    test_printer(
+        |pp| {
+            pp.text("a")?;
+            pp.space()?;
+            pp.text("b")
+        },
+        "a b",
+    );
 }
 
 #[test]
 fn test_spaces() {
-    test_printer(|pp| pp.spaces(5), "     ");
+    test_printer(
+        |pp| {
+            pp.text("a")?;
+            pp.spaces(5)?;
+            pp.text("b")
+        },
+        "a     b",
+    );
 }
 
 #[test]
@@ -123,3 +149,946 @@ fn test_break_indent() {
         "\n  Hello,\n    world!",
     );
 }
+
+#[test]
+fn test_text_unicode_width() {
+    test_printer(
+        |pp| {
+            pp.cgroup(2, |pp| {
+                pp.text("日本語の文字列")?;
+                pp.space()?;
+                pp.text("e\u{301}t\u{e9} 👨\u{200d}👩\u{200d}👧")?;
+                pp.space()?;
+                pp.text("x".repeat(12))
+            })
+        },
+        "日本語の文字列 e\u{301}t\u{e9} 👨\u{200d}👩\u{200d}👧 xxxxxxxxxxxx",
+    );
+}
+
+#[test]
+fn test_width_strategy() {
+    let mut pp = Printer::new(String::new(), 9).with_measure(elegance::WidthStrategy::Bytes);
+    pp.cgroup(0, |pp| {
+        pp.text("日本")?;
+        pp.space()?;
+        pp.text("語")
+    })
+    .unwrap();
+    assert_eq!(pp.finish().unwrap(), "日本\n語");
+}
+
+#[test]
+fn test_printer_send() {
+    fn assert_send<T: Send>(_: &T) {}
+    let pp = Printer::new(String::new(), 40).with_measure(|text: &str| text.len());
+    assert_send(&pp);
+    assert_send(&Printer::new(String::new(), 40));
+}
+
+#[test]
+fn test_multiline_text() {
+    test_printer(
+        |pp| {
+            pp.cgroup(2, |pp| {
+                pp.text("/**")?;
+                pp.space()?;
+                pp.text("foo\r\n bar\n")?;
+                pp.text("*/")
+            })
+        },
+        "/**\n  foo\n   bar\n  */",
+    );
+}
+
+#[test]
+fn test_multiline_text_breaks_groups() {
+    test_printer(
+        |pp| {
+            pp.igroup(2, |pp| {
+                pp.text("a")?;
+                pp.space()?;
+                pp.cgroup(0, |pp| {
+                    pp.text("b")?;
+                    pp.space()?;
+                    pp.text("c\nd")
+                })?;
+                pp.space()?;
+                pp.text("e")
+            })
+        },
+        "a b\n  c\n  d e",
+    );
+}
+
+#[test]
+fn test_verbatim() {
+    test_printer(
+        |pp| {
+            pp.cgroup(4, |pp| {
+                pp.text("x =")?;
+                pp.space()?;
+                pp.verbatim("r\"\n  foo\nbar\"")?;
+                pp.space()?;
+                pp.text("y")
+            })
+        },
+        "x =\n    r\"\n  foo\nbar\"\n    y",
+    );
+    // The whitespace at the end of verbatim lines is kept.
+    test_printer(
+        |pp| {
+            pp.text("let s = \"")?;
+            pp.verbatim("a  \n  \nb")?;
+            pp.text("\";")
+        },
+        "let s = \"a  \n  \nb\";",
+    );
+}
+
+#[test]
+fn test_conditional_width() {
+    // The trailing comma does not count towards the flat width.
+    test_printer(
+        |pp| {
+            pp.cgroup(2, |pp| {
+                pp.text("x".repeat(38))?;
+                pp.zero_break()?;
+                pp.text("y")?;
+                pp.if_break(",")?;
+                pp.if_flat("!")
+            })
+        },
+        &("x".repeat(38) + "y!"),
+    );
+    test_printer(
+        |pp| {
+            pp.cgroup(2, |pp| {
+                pp.text("x".repeat(38))?;
+                pp.zero_break()?;
+                pp.text("y")?;
+                pp.if_break(",")?;
+                pp.if_flat("!!")
+            })
+        },
+        &("x".repeat(38) + "\n  y,"),
+    );
+}
+
+#[test]
+fn test_conditional_nested() {
+    test_printer(
+        |pp| {
+            pp.cgroup(2, |pp| {
+                pp.text("x".repeat(30))?;
+                pp.space()?;
+                pp.cgroup(0, |pp| {
+                    pp.text("foo")?;
+                    pp.if_break(";")
+                })?;
+                pp.space()?;
+                pp.text("y".repeat(10))?;
+                pp.if_break(";")
+            })
+        },
+        &("x".repeat(30) + "\n  foo\n  " + &"y".repeat(10) + ";"),
+    );
+}
+
+#[test]
+fn test_text_break() {
+    test_printer(
+        |pp| {
+            pp.cgroup(4, |pp| {
+                pp.text("x".repeat(30))?;
+                pp.text_break(" + ", " +", "")?;
+                pp.text("y".repeat(10))
+            })
+        },
+        &("x".repeat(30) + " +\n    " + &"y".repeat(10)),
+    );
+    test_printer(
+        |pp| {
+            pp.cgroup(4, |pp| {
+                pp.text("x".repeat(30))?;
+                pp.text_break(" + ", "", "+ ")?;
+                pp.text("y".repeat(10))
+            })
+        },
+        &("x".repeat(30) + "\n    + " + &"y".repeat(10)),
+    );
+}
+
+#[test]
+fn test_text_break_width() {
+    // The flat text counts towards the width of the group, and the text
+    // after the line break counts towards the width of the new line.
+    test_printer(
+        |pp| {
+            pp.igroup(0, |pp| {
+                pp.text("x".repeat(37))?;
+                pp.text_break(" | ", "", "| ")?;
+                pp.text("y".repeat(37))?;
+                pp.space()?;
+                pp.text("z")
+            })
+        },
+        &("x".repeat(37) + "\n| " + &"y".repeat(37) + "\nz"),
+    );
+
+    // The text before the line break counts towards the width of the line.
+    let mut pp = Printer::new(String::new(), 10);
+    pp.cgroup(2, |pp| {
+        pp.span(0, |pp| {
+            pp.text("a".repeat(9))?;
+            pp.text_break(" ", " \\", "")
+        })?;
+        pp.text("b")
+    })
+    .unwrap();
+    let (output, source_map) = pp.finish_with_source_map().unwrap();
+    assert_eq!(output, "aaaaaaaaa \\\n  b");
+    let end = source_map.get(0).unwrap().end;
+    assert_eq!((end.column, end.offset), (11, 11));
+}
+
+#[test]
+fn test_align() {
+    test_printer(
+        |pp| {
+            pp.text("(define ")?;
+            pp.calign(0, |pp| {
+                pp.text("(foo a b)")?;
+                pp.zero_break()?;
+                pp.text("(")?;
+                pp.calign(0, |pp| {
+                    pp.text("bar")?;
+                    pp.space()?;
+                    pp.text("x".repeat(20))?;
+                    pp.space()?;
+                    pp.text("y".repeat(20))
+                })?;
+                pp.text(")")
+            })?;
+            pp.text(")")
+        },
+        "(define (foo a b)\n        (bar\n         xxxxxxxxxxxxxxxxxxxx\n         yyyyyyyyyyyyyyyyyyyy))",
+    );
+}
+
+#[test]
+fn test_align_indent() {
+    // An aligned group started on an indented line.
+    test_printer(
+        |pp| {
+            pp.cgroup(4, |pp| {
+                pp.text("fn")?;
+                pp.hard_break()?;
+                pp.text("let x = ")?;
+                pp.calign(2, |pp| {
+                    pp.text("foo")?;
+                    pp.hard_break()?;
+                    pp.text("bar")?;
+                    pp.scan_break(0, -2)?;
+                    pp.text("baz")
+                })?;
+                pp.hard_break()?;
+                pp.text("qux")
+            })
+        },
+        "fn\n    let x = foo\n              bar\n            baz\n    qux",
+    );
+}
+
+#[test]
+fn test_nest() {
+    test_printer(
+        |pp| {
+            pp.group(|pp| {
+                pp.text("[")?;
+                pp.nest(2, |pp| {
+                    pp.zero_break()?;
+                    pp.text("x".repeat(30))?;
+                    pp.text(",")?;
+                    pp.space()?;
+                    pp.nest(2, |pp| {
+                        pp.text("y".repeat(10))?;
+                        pp.space()?;
+                        pp.text("z")
+                    })
+                })?;
+                pp.zero_break()?;
+                pp.text("]")
+            })
+        },
+        &("[\n  ".to_string() + &"x".repeat(30) + ",\n  yyyyyyyyyy\n    z\n]"),
+    );
+}
+
+#[test]
+fn test_nest_in_group() {
+    // Breaks in a nested group are indented by the enclosing nest.
+    test_printer(
+        |pp| {
+            pp.text("a")?;
+            pp.nest(4, |pp| {
+                pp.cgroup(2, |pp| {
+                    pp.hard_break()?;
+                    pp.text("b")?;
+                    pp.hard_break()?;
+                    pp.text("c")
+                })
+            })?;
+            pp.hard_break()?;
+            pp.text("d")
+        },
+        "a\n      b\n      c\nd",
+    );
+}
+
+#[test]
+fn test_delimited() {
+    test_printer(
+        |pp| {
+            pp.delimited("{", "}", 4, ["a", "b"], ";", |pp, s| {
+                pp.delimited("(", ")", 2, 0..7, ",", |pp, _| pp.text(s.repeat(3)))
+            })
+        },
+        "{\n    (aaa, aaa, aaa, aaa, aaa, aaa, aaa);\n    (bbb, bbb, bbb, bbb, bbb, bbb, bbb)\n}",
+    );
+}
+
+#[test]
+fn test_delimited_inconsistent() {
+    let style = ListStyle {
+        consistent: false,
+        ..Default::default()
+    };
+    test_printer(
+        |pp| {
+            pp.delimited_with(style, "[", "]", 2, 0..15, ",", |pp, i| {
+                pp.text(format!("{i:03}"))
+            })
+        },
+        "[\n  000, 001, 002, 003, 004, 005, 006, 007,\n  008, 009, 010, 011, 012, 013, 014\n]",
+    );
+
+    // The trailing separator is written before the closing delimiter, which
+    // is on its own line.
+    let style = ListStyle {
+        consistent: false,
+        trailing: true,
+        ..Default::default()
+    };
+    test_printer_with(
+        Printer::new(String::new(), 11),
+        |pp| {
+            pp.delimited_with(style, "[", "]", 2, 1..=5, ",", |pp, i| {
+                pp.text(i.to_string())
+            })
+        },
+        "[\n  1, 2, 3,\n  4, 5,\n]",
+    );
+}
+
+#[test]
+fn test_delimited_empty() {
+    test_printer(
+        |pp| pp.delimited("[", "]", 2, 0..0, ",", |pp, i| pp.text(i.to_string())),
+        "[]",
+    );
+}
+
+#[test]
+fn test_fill() {
+    test_printer(
+        |pp| {
+            pp.text("let xs = ")?;
+            pp.ialign(0, |pp| {
+                pp.fill(0..12, ",", |pp, i| {
+                    if i % 4 == 3 {
+                        pp.delimited("[", "]", 2, 0..i, ",", |pp, j| pp.text(j.to_string()))
+                    } else {
+                        pp.text(i.to_string())
+                    }
+                })
+            })
+        },
+        "let xs = 0, 1, 2, [0, 1, 2], 4, 5, 6,\n         [0, 1, 2, 3, 4, 5, 6], 8, 9,\n         10,\n         [\n           0,\n           1,\n           2,\n           3,\n           4,\n           5,\n           6,\n           7,\n           8,\n           9,\n           10\n         ]",
+    );
+}
+
+#[test]
+fn test_words() {
+    let text = format!("error: expected `{}`, found\tnewline\n", "x".repeat(45));
+    test_printer(
+        |pp| {
+            pp.text("- ")?;
+            pp.nest(2, |pp| pp.words(text))?;
+            pp.text(".")
+        },
+        &("- error: expected\n  `".to_string() + &"x".repeat(45) + "`,\n  found newline."),
+    );
+}
+
+#[test]
+fn test_prefix() {
+    test_printer(
+        |pp| {
+            pp.text("fn main() {")?;
+            pp.nest(4, |pp| {
+                pp.hard_break()?;
+                pp.with_prefix("// ", |pp| {
+                    pp.text("if x {")?;
+                    pp.nest(4, |pp| {
+                        pp.hard_break()?;
+                        pp.words("the quick brown fox jumps over the lazy dog")
+                    })?;
+                    pp.hard_break()?;
+                    pp.text("}")
+                })?;
+                pp.hard_break()?;
+                pp.text("foo();")
+            })?;
+            pp.hard_break()?;
+            pp.text("}")
+        },
+        "fn main() {\n//     if x {\n//         the quick brown fox jumps\n//         over the lazy dog\n//     }\n    foo();\n}",
+    );
+}
+
+#[test]
+fn test_prefix_align() {
+    test_printer(
+        |pp| {
+            pp.with_prefix("> ", |pp| {
+                pp.text("foo(")?;
+                pp.calign(0, |pp| {
+                    pp.text("x".repeat(31))?;
+                    pp.text(",")?;
+                    pp.space()?;
+                    pp.text("y)")
+                })
+            })
+        },
+        &("> foo(".to_string() + &"x".repeat(31) + ",\n>     y)"),
+    );
+}
+
+#[test]
+fn test_prefix_end_of_line() {
+    // The line after a block that ends with a line break has no prefix.
+    let print = |pp: &mut Printer| {
+        pp.with_prefix("// ", |pp| {
+            pp.text("a")?;
+            pp.hard_break()
+        })?;
+        pp.with_prefix("// ", |pp| pp.text("b\n"))?;
+        pp.text("c")
+    };
+    test_printer(print, "// a\n// b\nc");
+    test_printer_with(
+        Printer::new(String::new(), 40).with_max_blank_lines(1),
+        print,
+        "// a\n// b\nc",
+    );
+}
+
+#[test]
+fn test_prefix_mid_line() {
+    // The prefix is not written on the line where the block begins, so it
+    // does not take up width there.
+    test_printer_with(
+        Printer::new(String::new(), 12),
+        |pp| {
+            pp.cgroup(0, |pp| {
+                pp.text("foo")?;
+                pp.space()?;
+                pp.with_prefix("//////////", |pp| pp.text("bar"))
+            })
+        },
+        "foo bar",
+    );
+    test_printer_with(
+        Printer::new(String::new(), 12),
+        |pp| {
+            pp.cgroup(0, |pp| {
+                pp.text("foo")?;
+                pp.hard_break()?;
+                pp.with_prefix("//////////", |pp| pp.text("bar"))
+            })
+        },
+        "foo\n//////////bar",
+    );
+}
+
+#[test]
+fn test_indent_tabs() {
+    test_printer_with(
+        Printer::new(String::new(), 20).with_indent_style(IndentStyle::Tabs(4)),
+        |pp| {
+            pp.text("a")?;
+            pp.nest(4, |pp| {
+                pp.hard_break()?;
+                pp.text("b")?;
+                pp.nest(6, |pp| {
+                    pp.hard_break()?;
+                    pp.text("c".repeat(7))?;
+                    pp.space()?;
+                    pp.text("d")
+                })
+            })
+        },
+        "a\n\tb\n\t\t  ccccccc d",
+    );
+}
+
+#[test]
+fn test_indent_tabs_width() {
+    // Tabs count as their width when deciding whether groups fit.
+    test_printer_with(
+        Printer::new(String::new(), 20).with_indent_style(IndentStyle::Tabs(8)),
+        |pp| {
+            pp.nest(16, |pp| {
+                pp.hard_break()?;
+                pp.cgroup(0, |pp| {
+                    pp.text("x")?;
+                    pp.space()?;
+                    pp.text("y".repeat(2))
+                })?;
+                pp.hard_break()?;
+                pp.cgroup(0, |pp| {
+                    pp.text("x")?;
+                    pp.space()?;
+                    pp.text("y".repeat(3))
+                })
+            })
+        },
+        "\n\t\tx yy\n\t\tx\n\t\tyyy",
+    );
+}
+
+#[test]
+fn test_indent_smart_tabs() {
+    test_printer_with(
+        Printer::new(String::new(), 20).with_indent_style(IndentStyle::SmartTabs(4)),
+        |pp| {
+            pp.text("{")?;
+            pp.nest(4, |pp| {
+                pp.hard_break()?;
+                pp.text("foo(")?;
+                pp.calign(0, |pp| {
+                    pp.text("a,")?;
+                    pp.hard_break()?;
+                    pp.text("b,")?;
+                    pp.nest(4, |pp| {
+                        pp.hard_break()?;
+                        pp.text("c")
+                    })
+                })?;
+                pp.text(")")
+            })?;
+            pp.hard_break()?;
+            pp.text("}")
+        },
+        "{\n\tfoo(a,\n\t    b,\n\t        c)\n}",
+    );
+}
+
+#[test]
+fn test_newline() {
+    test_printer_with(
+        Printer::new(String::new(), 10).with_newline("\r\n"),
+        |pp| {
+            pp.with_prefix("# ", |pp| {
+                pp.cgroup(2, |pp| {
+                    pp.text("foo")?;
+                    pp.space()?;
+                    pp.text("bar\nbaz")?;
+                    pp.verbatim("\nqux")
+                })
+            })
+        },
+        "# foo\r\n#   bar\r\n#   baz\r\n# qux",
+    );
+}
+
+#[test]
+fn test_trailing_whitespace() {
+    test_printer(
+        |pp| {
+            pp.with_prefix("// ", |pp| {
+                pp.text("foo ")?;
+                pp.hard_break()?;
+                pp.hard_break()?;
+                pp.nest(4, |pp| {
+                    pp.text("bar")?;
+                    pp.spaces(3)?;
+                    pp.hard_break()?;
+                    pp.text(" \t")?;
+                    pp.hard_break()?;
+                    pp.text("baz")
+                })
+            })?;
+            pp.text(" ")
+        },
+        "// foo\n//\n// bar\n//\n//     baz",
+    );
+}
+
+#[test]
+fn test_max_blank_lines() {
+    test_printer_with(
+        Printer::new(String::new(), 40).with_max_blank_lines(0),
+        |pp| {
+            pp.cgroup(0, |pp| {
+                pp.text("a")?;
+                pp.zero_break()?;
+                pp.space()?;
+                pp.hard_break()?;
+                pp.blank_line()?;
+                pp.text("b")
+            })
+        },
+        "a\nb",
+    );
+    test_printer_with(
+        Printer::new(String::new(), 40).with_max_blank_lines(1),
+        |pp| {
+            pp.with_prefix("// ", |pp| {
+                pp.text("a")?;
+                pp.blank_line()?;
+                pp.blank_line()?;
+                pp.verbatim("b\n\n\nc")?;
+                pp.blank_line()
+            })
+        },
+        "// a\n//\n// b\n//\n//\n// c\n",
+    );
+}
+
+#[test]
+fn test_max_blank_lines_start() {
+    test_printer_with(
+        Printer::new(String::new(), 40).with_max_blank_lines(1),
+        |pp| {
+            pp.blank_line()?;
+            pp.blank_line()?;
+            pp.text("a")?;
+            pp.blank_line()?;
+            pp.blank_line()?;
+            pp.text("b")
+        },
+        "a\n\nb",
+    );
+}
+
+#[test]
+fn test_max_blank_lines_block() {
+    test_printer_with(
+        Printer::new(String::new(), 40).with_max_blank_lines(2),
+        |pp| {
+            for name in ["foo", "bar"] {
+                pp.text(format!("fn {name}() {{"))?;
+                pp.nest(4, |pp| {
+                    pp.blank_line()?;
+                    pp.text("x;")?;
+                    pp.blank_line()?;
+                    pp.blank_line()?;
+                    pp.text("y;")?;
+                    pp.blank_line()
+                })?;
+                pp.hard_break()?;
+                pp.text("}")?;
+                pp.blank_line()?;
+                pp.blank_line()?;
+            }
+            Ok(())
+        },
+        "fn foo() {\n    x;\n\n\n    y;\n}\n\n\nfn bar() {\n    x;\n\n\n    y;\n}\n",
+    );
+    // The blank lines after a block are kept.
+    test_printer_with(
+        Printer::new(String::new(), 40).with_max_blank_lines(1),
+        |pp| {
+            pp.text("def f():")?;
+            pp.nest(4, |pp| {
+                pp.hard_break()?;
+                pp.text("x")?;
+                pp.blank_line()
+            })?;
+            pp.blank_line()?;
+            pp.text("y")
+        },
+        "def f():\n    x\n\ny",
+    );
+}
+
+#[test]
+fn test_ribbon_width() {
+    test_printer_with(
+        Printer::new(String::new(), 40).with_ribbon_width(20),
+        |pp| {
+            pp.with_prefix("// ", |pp| {
+                pp.text("fn foo() {")?;
+                pp.nest(12, |pp| {
+                    pp.hard_break()?;
+                    pp.cgroup(4, |pp| {
+                        pp.text("x".repeat(12))?;
+                        pp.space()?;
+                        pp.text("y".repeat(7))
+                    })?;
+                    pp.hard_break()?;
+                    pp.cgroup(4, |pp| {
+                        pp.text("x".repeat(12))?;
+                        pp.space()?;
+                        pp.text("y".repeat(8))
+                    })
+                })
+            })
+        },
+        "// fn foo() {\n//             xxxxxxxxxxxx yyyyyyy\n//             xxxxxxxxxxxx\n//                 yyyyyyyy",
+    );
+}
+
+#[test]
+fn test_indent_overflow() {
+    let nested = |pp: &mut Printer| {
+        pp.text("a")?;
+        pp.nest(4, |pp| {
+            pp.hard_break()?;
+            pp.text("b")?;
+            pp.nest(4, |pp| {
+                pp.hard_break()?;
+                pp.text("c")?;
+                pp.nest(4, |pp| {
+                    pp.hard_break()?;
+                    pp.cgroup(0, |pp| {
+                        pp.text("x".repeat(20))?;
+                        pp.space()?;
+                        pp.text("y")
+                    })
+                })
+            })
+        })
+    };
+    test_printer_with(
+        Printer::new(String::new(), 24).with_indent_overflow(IndentOverflow::Cap(6)),
+        nested,
+        "a\n    b\n      c\n      xxxxxxxxxxxxxxxxxxxx\n      y",
+    );
+    test_printer_with(
+        Printer::new(String::new(), 24).with_indent_overflow(IndentOverflow::Wrap {
+            max: 6,
+            marker: "| ".into(),
+        }),
+        nested,
+        "a\n    b\n|   c\n| xxxxxxxxxxxxxxxxxxxx y",
+    );
+}
+
+#[test]
+fn test_deep_nesting() {
+    const DEPTH: usize = 100_000;
+    test_printer(
+        |pp| {
+            for _ in 0..DEPTH {
+                pp.scan_begin(0, true);
+            }
+            pp.text("x")?;
+            for _ in 0..DEPTH {
+                pp.scan_end()?;
+            }
+            for _ in 0..DEPTH {
+                pp.scan_begin(0, true);
+                pp.text("(a . ")?;
+            }
+            pp.text("nil")?;
+            for _ in 0..DEPTH {
+                pp.text(")")?;
+                pp.scan_end()?;
+            }
+            Ok(())
+        },
+        &("x".to_string() + &"(a . ".repeat(DEPTH) + "nil" + &")".repeat(DEPTH)),
+    );
+}
+
+#[test]
+fn test_max_buffered() {
+    let print = |pp: &mut Printer| {
+        pp.igroup(0, |pp| {
+            for i in 0..1000 {
+                pp.cgroup(2, |pp| {
+                    pp.text(i.to_string())?;
+                    pp.zero_break()?;
+                    pp.text(",")
+                })?;
+                pp.space()?;
+            }
+            Ok(())
+        })
+    };
+    let mut pp = Printer::new(String::new(), Printer::<String>::MAX_WIDTH);
+    print(&mut pp).unwrap();
+    assert!(pp.buffer_stats().peak_tokens > 4000);
+    let expected = pp.finish().unwrap();
+
+    let mut pp = Printer::new(String::new(), Printer::<String>::MAX_WIDTH).with_max_buffered(100);
+    print(&mut pp).unwrap();
+    let stats = pp.buffer_stats();
+    assert!(stats.peak_tokens <= 100);
+    assert_eq!(stats.tokens, 0);
+    assert_eq!(pp.finish().unwrap(), expected);
+}
+
+#[test]
+fn test_flush() -> Result<(), ()> {
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Default)]
+    struct Shared(Rc<RefCell<String>>, Rc<RefCell<usize>>);
+
+    impl Render for Shared {
+        type Error = ();
+        type Annotation = ();
+
+        fn write_str(&mut self, s: &str) -> Result<(), ()> {
+            self.0.borrow_mut().push_str(s);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), ()> {
+            *self.1.borrow_mut() += 1;
+            Ok(())
+        }
+    }
+
+    let shared = Shared::default();
+    let (output, flushes) = (shared.0.clone(), shared.1.clone());
+    let mut pp = Printer::new(shared, 40);
+    pp.text("> ")?;
+    pp.calign(0, |pp| {
+        pp.cgroup(0, |pp| {
+            pp.text("[1,")?;
+            pp.space()?;
+            pp.text("2]")
+        })?;
+        pp.text(",")?;
+        pp.space()?;
+        assert_eq!(*output.borrow(), "");
+        pp.flush()?;
+        assert_eq!(*output.borrow(), "> [1, 2],\n");
+        assert_eq!(*flushes.borrow(), 1);
+        pp.text("3")
+    })?;
+    pp.finish()?;
+    assert_eq!(*output.borrow(), "> [1, 2],\n  3");
+    Ok(())
+}
+
+#[test]
+fn test_annotate() {
+    let mut pp = Printer::new(Markup::default(), 16);
+    pp.annotate("call", |pp| {
+        pp.annotate("name", |pp| pp.text("foo"))?;
+        pp.text("(")?;
+        pp.calign(0, |pp| {
+            pp.annotate("arg", |pp| pp.text("x".repeat(8)))?;
+            pp.text(",")?;
+            pp.space()?;
+            pp.annotate("empty", |pp| pp.annotate("nested", |pp| pp.text("  ")))?;
+            pp.annotate("arg", |pp| {
+                pp.text("y")?;
+                pp.hard_break()?;
+                pp.text("z")
+            })
+        })?;
+        pp.text(")")
+    })
+    .unwrap();
+    assert_eq!(
+        pp.finish().unwrap().0,
+        "<call><name>foo</name>(<arg>xxxxxxxx</arg>,\n      <arg>y\n    z</arg>)</call>"
+    );
+}
+
+#[test]
+fn test_source_map() {
+    let mut pp = Printer::new(String::new(), 20).with_newline("\r\n");
+    pp.span(0, |pp| {
+        pp.text("let x = ")?;
+        pp.nest(4, |pp| {
+            pp.cgroup(0, |pp| {
+                pp.span(1, |pp| pp.text("日本"))?;
+                pp.text(" + ")?;
+                pp.span(2, |pp| {
+                    pp.hard_break()?;
+                    pp.text("y".repeat(8))?;
+                    pp.text("    ")
+                })?;
+                pp.span(3, |pp| pp.text("  "))?;
+                pp.hard_break()?;
+                pp.mark(4)
+            })
+        })?;
+        pp.text("z;")
+    })
+    .unwrap();
+    pp.mark(5).unwrap();
+    let (output, source_map) = pp.finish_with_source_map().unwrap();
+    assert_eq!(output, "let x = 日本 +\r\n    yyyyyyyy\r\n    z;");
+
+    let location = |line, column, offset| elegance::Location {
+        line,
+        column,
+        offset,
+    };
+    let span = |id| {
+        let span = source_map.get(id).unwrap();
+        (span.start, span.end)
+    };
+    assert_eq!(source_map.len(), 6);
+    assert_eq!(span(0), (location(0, 0, 0), location(2, 6, 38)));
+    assert_eq!(span(1), (location(0, 8, 8), location(0, 12, 14)));
+    assert_eq!(span(2), (location(1, 4, 22), location(1, 12, 30)));
+    assert_eq!(span(3), (location(2, 4, 36), location(2, 4, 36)));
+    assert_eq!(span(4), (location(2, 4, 36), location(2, 4, 36)));
+    assert_eq!(span(5), (location(2, 6, 38), location(2, 6, 38)));
+}
+
+#[test]
+fn test_cursor() {
+    let mut pp = Printer::new(String::new(), 40);
+    pp.cgroup(4, |pp| {
+        pp.text("fn f() {")?;
+        pp.cursor(0)?;
+        pp.hard_break()?;
+        pp.text("x; ")?;
+        pp.cursor(1)?;
+        pp.hard_break()?;
+        pp.cursor(2)?;
+        pp.text("y = ")?;
+        pp.cgroup(0, |pp| {
+            pp.text("[1,")?;
+            pp.space()?;
+            pp.cursor(3)?;
+            pp.text("2]")
+        })
+    })
+    .unwrap();
+    pp.cursor(4).unwrap();
+    let (output, source_map) = pp.finish_with_source_map().unwrap();
+    assert_eq!(output, "fn f() {\n    x;\n    y = [1, 2]");
+
+    let cursor = |id| {
+        let span = source_map.get(id).unwrap();
+        assert_eq!(span.start, span.end);
+        (span.start.line, span.start.column, span.start.offset)
+    };
+    assert_eq!(cursor(0), (0, 8, 8));
+    assert_eq!(cursor(1), (1, 6, 15));
+    assert_eq!(cursor(2), (2, 4, 20));
+    assert_eq!(cursor(3), (2, 12, 28));
+    assert_eq!(cursor(4), (2, 14, 30));
+}
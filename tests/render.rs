@@ -0,0 +1,124 @@
+use elegance::{
+    render::{
+        ansi::{Ansi, Color, Style},
+        html::Html,
+    },
+    Printer,
+};
+
+#[test]
+fn test_ansi() -> Result<(), ()> {
+    let string = Style {
+        fg: Some(Color::Rgb(0, 128, 0)),
+        ..Default::default()
+    };
+    let link = Style {
+        underline: true,
+        link: Some("https://example.com".to_string()),
+        ..Default::default()
+    };
+    let mut pp = Printer::new(Ansi::new(String::new()), 20);
+    pp.text("x =")?;
+    pp.nest(4, |pp| {
+        pp.annotate(string, |pp| {
+            pp.hard_break()?;
+            pp.text("\"see ")?;
+            pp.annotate(link, |pp| pp.text("example.com"))?;
+            pp.space()?;
+            pp.text("for")?;
+            pp.hard_break()?;
+            pp.text("details\"")
+        })
+    })?;
+    assert_eq!(
+        pp.finish()?.into_inner(),
+        "x =\n    \x1b[38;2;0;128;0m\"see \x1b[0m\x1b[4;38;2;0;128;0m\x1b]8;;https://example.com\x1b\\example.com\x1b[0m\x1b]8;;\x1b\\\n    \x1b[38;2;0;128;0mfor\x1b[0m\n    \x1b[38;2;0;128;0mdetails\"\x1b[0m"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_ansi_nested() -> Result<(), ()> {
+    // The whitespace after a nested region is written in the outer style.
+    let outer = Style {
+        bg: Some(Color::Blue),
+        ..Default::default()
+    };
+    let inner = Style {
+        underline: true,
+        ..Default::default()
+    };
+    let mut pp = Printer::new(Ansi::new(String::new()), 20);
+    pp.annotate(outer, |pp| {
+        pp.annotate(inner, |pp| pp.text("let"))?;
+        pp.space()?;
+        pp.text("x")
+    })?;
+    assert_eq!(
+        pp.finish()?.into_inner(),
+        "\x1b[4;44mlet\x1b[0m\x1b[44m x\x1b[0m"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_ansi_prefix() -> Result<(), ()> {
+    // The prefix is not part of the annotated region.
+    let comment = Style {
+        fg: Some(Color::Green),
+        ..Default::default()
+    };
+    let mut pp = Printer::new(Ansi::new(String::new()), 20);
+    pp.with_prefix("> ", |pp| {
+        pp.annotate(comment, |pp| {
+            pp.text("a")?;
+            pp.hard_break()?;
+            pp.text("b")
+        })
+    })?;
+    assert_eq!(
+        pp.finish()?.into_inner(),
+        "> \x1b[32ma\x1b[0m\n> \x1b[32mb\x1b[0m"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_html() -> Result<(), ()> {
+    // The escaped text is wider, but the call still fits on the line.
+    let mut pp = Printer::new(Html::new(String::new()), 15);
+    pp.annotate("call".into(), |pp| {
+        pp.text("f(")?;
+        pp.calign(0, |pp| {
+            pp.annotate("str".into(), |pp| pp.text("\"<a&b>\","))?;
+            pp.space()?;
+            pp.annotate("str".into(), |pp| pp.text("'c'"))
+        })?;
+        pp.text(")")?;
+        pp.hard_break()?;
+        pp.text(";")
+    })?;
+    assert_eq!(
+        pp.finish()?.finish()?,
+        "<pre><span class=\"call\">f(<span class=\"str\">&quot;&lt;a&amp;b&gt;&quot;,</span> <span class=\"str\">&#39;c&#39;</span>)</span>\n<span class=\"call\">;</span></pre>"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_html_prefix() -> Result<(), ()> {
+    // The spans are opened again after the prefix.
+    let mut pp = Printer::new(Html::new(String::new()), 20);
+    pp.with_prefix("> ", |pp| {
+        pp.annotate("s".into(), |pp| {
+            pp.text("a")?;
+            pp.hard_break()?;
+            pp.text("b")
+        })
+    })?;
+    assert_eq!(
+        pp.finish()?.finish()?,
+        "<pre>&gt; <span class=\"s\">a</span>\n&gt; <span class=\"s\">b</span></pre>"
+    );
+    Ok(())
+}